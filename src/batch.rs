@@ -0,0 +1,148 @@
+//! Runs independent commands concurrently.
+
+use std::env;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::builder::SimpleCommand;
+use crate::error::SimpleCommandError;
+use crate::glob::Glob;
+use crate::jobserver;
+use crate::output::Output;
+use crate::script;
+
+#[derive(Debug)]
+enum Job {
+    Script(String),
+    Command(Box<SimpleCommand>),
+}
+
+/// A batch of independent commands that are run concurrently.
+///
+/// Every command runs to completion even if others fail, and the failures are then reported
+/// together.
+/// By default as many commands run at once as cargo allows jobs through `NUM_JOBS`.
+/// When cargo provides a jobserver every command beyond the first also waits for a token from
+/// it, so the batch shares the job limit with the rest of the build.
+///
+/// ```no_run
+/// use simple_command::{Batch, SimpleCommand};
+///
+/// let mut bindgen = SimpleCommand::new("bindgen");
+/// bindgen.arg("wrapper.h");
+///
+/// Batch::new()
+///     .add("protoc --rust_out=src/generated schema.proto")
+///     .add("flatc --rust -o src/generated schema.fbs")
+///     .add_command(bindgen)
+///     .run();
+/// ```
+#[derive(Debug, Default)]
+pub struct Batch {
+    jobs: Vec<Job>,
+    workers: Option<usize>,
+    glob: Glob,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch::default()
+    }
+
+    /// Adds a command string, run the same way as `simple_command`.
+    pub fn add(&mut self, cmd: &str) -> &mut Batch {
+        self.jobs.push(Job::Script(cmd.to_string()));
+        self
+    }
+
+    /// Adds a command configured with the `SimpleCommand` builder.
+    pub fn add_command(&mut self, command: SimpleCommand) -> &mut Batch {
+        self.jobs.push(Job::Command(Box::new(command)));
+        self
+    }
+
+    /// Expands glob patterns in the command strings added with `add` like
+    /// `simple_command_with_glob`, by default they are passed through as written.
+    pub fn glob(&mut self, glob: Glob) -> &mut Batch {
+        self.glob = glob;
+        self
+    }
+
+    /// Sets the maximum number of commands that run at once.
+    pub fn jobs(&mut self, jobs: usize) -> &mut Batch {
+        self.workers = Some(jobs.max(1));
+        self
+    }
+
+    /// Runs every command, panicking if any of them failed.
+    ///
+    /// The outputs are returned in the order the commands were added.
+    pub fn run(&mut self) -> Vec<Output> {
+        match self.try_run() {
+            Ok(outputs) => outputs,
+            Err(err) => panic!("\n{}", err)
+        }
+    }
+
+    /// Runs every command, returning every failure instead of panicking.
+    pub fn try_run(&mut self) -> Result<Vec<Output>, SimpleCommandError> {
+        let total = self.jobs.len();
+        let workers = self.workers.unwrap_or_else(default_jobs).min(total);
+        let glob = self.glob;
+        let queue = &Mutex::new(self.jobs.iter_mut().enumerate());
+
+        let mut results: Vec<(usize, Result<Output, SimpleCommandError>)> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|worker| scope.spawn(move || {
+                    let mut results = Vec::new();
+                    loop {
+                        // The lock is released before waiting for a token and running the job
+                        let next = queue.lock().unwrap().next();
+                        let (i, job) = match next {
+                            Some(next) => next,
+                            None => return results,
+                        };
+                        // The build script already holds a token for the first worker.
+                        // The job is taken before waiting for a token, so a worker that finds the
+                        // queue empty exits rather than holding up the batch on the jobserver.
+                        let _token = match jobserver::get() {
+                            Some(jobserver) if worker > 0 => jobserver.acquire(),
+                            _ => None,
+                        };
+                        let result = match job {
+                            Job::Script(cmd) => script::run_script(cmd, glob),
+                            Job::Command(command) => command.try_run(),
+                        };
+                        results.push((i, result));
+                    }
+                }))
+                .collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+        results.sort_by_key(|&(i, _)| i);
+
+        let mut outputs = Vec::new();
+        let mut failures = Vec::new();
+        for (_, result) in results {
+            match result {
+                Ok(output) => outputs.push(output),
+                Err(err) => failures.push(err),
+            }
+        }
+        if failures.is_empty() {
+            Ok(outputs)
+        }
+        else {
+            Err(SimpleCommandError::BatchFailed { total, failures })
+        }
+    }
+}
+
+/// The number of jobs cargo allows the build script to run, or the number of CPUs outside of cargo.
+fn default_jobs() -> usize {
+    env::var("NUM_JOBS").ok()
+        .and_then(|jobs| jobs.parse().ok())
+        .or_else(|| thread::available_parallelism().ok().map(|jobs| jobs.get()))
+        .unwrap_or(1)
+        .max(1)
+}
@@ -0,0 +1,357 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cargo::{self, CargoWarnings};
+use crate::error::SimpleCommandError;
+use crate::glob::{self, Glob};
+use crate::output::Output;
+use crate::parse::{parse_pipeline, quote, Pipeline, Word};
+use crate::redirect::Redirect;
+use crate::retry::{self, Retry};
+use crate::run::{self, Options, Stdin};
+use crate::script::Shell;
+
+/// A builder for commands that need more than a single string to describe them.
+///
+/// The methods mirror those of `std::process::Command`.
+/// Running the command captures its output and panics on failure exactly like `simple_command`.
+///
+/// Commands can be chained into a pipeline with `pipe`, after which `arg`, `env` and friends
+/// configure the command that was piped into.
+///
+/// ```no_run
+/// use simple_command::SimpleCommand;
+///
+/// SimpleCommand::new("protoc")
+///     .arg("--rust_out=src/generated")
+///     .args(&["schema/a.proto", "schema/b.proto"])
+///     .env("PROTOC_INCLUDE", "schema")
+///     .current_dir("..")
+///     .run();
+/// ```
+#[derive(Debug)]
+pub struct SimpleCommand {
+    /// The commands of the pipeline, there is always at least one.
+    commands: Vec<Command>,
+    /// The command string this was parsed from, used to refer to the command in errors.
+    cmd: Option<String>,
+    options: Options,
+    retry: Option<Retry>,
+}
+
+impl SimpleCommand {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> SimpleCommand {
+        SimpleCommand {
+            commands: vec![Command::new(program)],
+            cmd: None,
+            options: Options {
+                stdin: Some(Stdin::Null),
+                ..Options::default()
+            },
+            retry: None,
+        }
+    }
+
+    /// Creates a command from a command string split the same way as `simple_command`.
+    ///
+    /// Environment variables are expanded after the string is split into words, so a value
+    /// containing spaces still forms a single argument.
+    /// Scripts combining commands with `&&`, `||` or `;` are only supported by `simple_command`.
+    pub fn parse(cmd: &str) -> Result<SimpleCommand, SimpleCommandError> {
+        SimpleCommand::parse_with_glob(cmd, Glob::Disabled)
+    }
+
+    /// Creates a command from a command string like `parse`, also expanding unquoted glob patterns
+    /// such as `proto/*.proto` or `src/**/*.rs` as configured by `glob`.
+    ///
+    /// ```no_run
+    /// use simple_command::{Glob, SimpleCommand};
+    ///
+    /// SimpleCommand::parse_with_glob("protoc --rust_out=$OUT_DIR proto/*.proto", Glob::Enabled)
+    ///     .unwrap()
+    ///     .run();
+    /// ```
+    pub fn parse_with_glob(cmd: &str, glob: Glob) -> Result<SimpleCommand, SimpleCommandError> {
+        SimpleCommand::from_pipeline(&parse_pipeline(cmd)?, cmd, glob, &Shell::default())
+    }
+
+    /// Creates a command from a parsed pipeline, expanding variables and globs in its words.
+    ///
+    /// `cmd` is the text of the pipeline, used to refer to the command in errors.
+    /// Every command runs in the working directory and with the variables set by `shell`.
+    pub(crate) fn from_pipeline(
+        pipeline: &Pipeline,
+        cmd: &str,
+        glob: Glob,
+        shell: &Shell,
+    ) -> Result<SimpleCommand, SimpleCommandError> {
+        let expand = |word: &Word| {
+            word.expand(shell).map_err(|name| SimpleCommandError::MissingEnvVar { cmd: cmd.to_string(), name })
+        };
+        let redirect = |redirect: &Option<(Word, bool)>| -> Result<_, SimpleCommandError> {
+            Ok(match redirect {
+                Some((path, false)) => Some(Redirect::Truncate(shell.path(expand(path)?))),
+                Some((path, true)) => Some(Redirect::Append(shell.path(expand(path)?))),
+                None => None,
+            })
+        };
+
+        let mut command: Option<SimpleCommand> = None;
+        for words in &pipeline.stages {
+            let mut expanded = Vec::new();
+            for word in words {
+                if glob == Glob::Disabled || !glob::is_glob(word) {
+                    expanded.push(expand(word)?);
+                    continue;
+                }
+
+                let paths = glob::expand(word, shell)
+                    .map_err(|name| SimpleCommandError::MissingEnvVar { cmd: cmd.to_string(), name })?;
+                if paths.is_empty() && glob == Glob::Enabled {
+                    return Err(SimpleCommandError::NoGlobMatch { cmd: cmd.to_string(), pattern: word.literal() });
+                }
+                expanded.extend(paths);
+            }
+            let words = expanded;
+            let stage = match words.split_first() {
+                Some((program, args)) => {
+                    let mut stage = SimpleCommand::new(program);
+                    stage.args(args).envs(&shell.vars);
+                    if let Some(dir) = &shell.cwd {
+                        stage.current_dir(dir);
+                    }
+                    stage
+                }
+                None => return Err(SimpleCommandError::NoCommand),
+            };
+            match &mut command {
+                Some(command) => {
+                    command.pipe(stage);
+                }
+                None => command = Some(stage),
+            }
+        }
+        let mut command = command.unwrap();
+        command.options.stdout = redirect(&pipeline.stdout)?;
+        command.options.stderr = redirect(&pipeline.stderr)?;
+        command.cmd = Some(cmd.to_string());
+        Ok(command)
+    }
+
+    /// Connects the stdout of this command to the stdin of `next`, forming a pipeline.
+    ///
+    /// The pipeline fails if any of its commands fail, and the failure identifies which one.
+    /// Only the commands of `next` are used, options such as `timeout` apply to the whole
+    /// pipeline and are taken from `self`.
+    ///
+    /// ```no_run
+    /// use simple_command::SimpleCommand;
+    ///
+    /// SimpleCommand::new("git")
+    ///     .arg("ls-files")
+    ///     .pipe(SimpleCommand::new("xargs"))
+    ///     .arg("sha256sum")
+    ///     .run();
+    /// ```
+    pub fn pipe(&mut self, next: SimpleCommand) -> &mut SimpleCommand {
+        self.commands.extend(next.commands);
+        self.cmd = None;
+        self
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut SimpleCommand {
+        self.last().arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut SimpleCommand
+        where I: IntoIterator<Item = S>, S: AsRef<OsStr>
+    {
+        self.last().args(args);
+        self
+    }
+
+    pub fn env<K, V>(&mut self, key: K, val: V) -> &mut SimpleCommand
+        where K: AsRef<OsStr>, V: AsRef<OsStr>
+    {
+        self.last().env(key, val);
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut SimpleCommand
+        where I: IntoIterator<Item = (K, V)>, K: AsRef<OsStr>, V: AsRef<OsStr>
+    {
+        self.last().envs(vars);
+        self
+    }
+
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut SimpleCommand {
+        self.last().env_remove(key);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut SimpleCommand {
+        self.last().env_clear();
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut SimpleCommand {
+        self.last().current_dir(dir);
+        self
+    }
+
+    /// Forwards lines the command writes to stderr as `cargo:warning=` directives.
+    ///
+    /// This makes warnings from tools such as `protoc` or `nasm` visible without failing the build.
+    pub fn cargo_warnings(&mut self, warnings: CargoWarnings) -> &mut SimpleCommand {
+        self.options.cargo_warnings = warnings;
+        self
+    }
+
+    /// Declares a file or directory the command reads from.
+    ///
+    /// Running the command emits `cargo:rerun-if-changed` for the input.
+    /// Directories are expanded recursively so every file inside is tracked.
+    pub fn input<P: AsRef<Path>>(&mut self, path: P) -> &mut SimpleCommand {
+        self.options.inputs.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn inputs<I, P>(&mut self, paths: I) -> &mut SimpleCommand
+        where I: IntoIterator<Item = P>, P: AsRef<Path>
+    {
+        self.options.inputs.extend(paths.into_iter().map(|path| path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Declares an environment variable the command depends on.
+    ///
+    /// Running the command emits `cargo:rerun-if-env-changed` for the variable.
+    pub fn env_input<K: AsRef<OsStr>>(&mut self, key: K) -> &mut SimpleCommand {
+        self.options.env_inputs.push(key.as_ref().to_os_string());
+        self
+    }
+
+    /// Kills the command, along with any processes it spawned, if it has not finished after `timeout`.
+    ///
+    /// The failure then reports the output captured up until the command was killed.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut SimpleCommand {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets where the command reads its stdin from, by default stdin is empty.
+    ///
+    /// ```no_run
+    /// use simple_command::{SimpleCommand, Stdin};
+    ///
+    /// SimpleCommand::new("sqlite3")
+    ///     .arg("db.sqlite")
+    ///     .stdin(Stdin::Bytes(b"CREATE TABLE foo (id INTEGER);".to_vec()))
+    ///     .run();
+    /// ```
+    pub fn stdin(&mut self, stdin: Stdin) -> &mut SimpleCommand {
+        self.options.stdin = Some(stdin);
+        self
+    }
+
+    /// Redirects stdout into a file instead of capturing it.
+    ///
+    /// For a pipeline this is the stdout of the last command.
+    pub fn stdout(&mut self, redirect: Redirect) -> &mut SimpleCommand {
+        self.options.stdout = Some(redirect);
+        self
+    }
+
+    /// Redirects stderr into a file instead of capturing it.
+    ///
+    /// For a pipeline this is the stderr of every command.
+    /// Redirecting to the same path as stdout writes both streams into that one file.
+    pub fn stderr(&mut self, redirect: Redirect) -> &mut SimpleCommand {
+        self.options.stderr = Some(redirect);
+        self
+    }
+
+    /// Accepts the return values as success in addition to 0, e.g. 1 for `diff` finding differences.
+    ///
+    /// For a pipeline the return values are accepted from every command.
+    pub fn success_codes<I: IntoIterator<Item = i32>>(&mut self, codes: I) -> &mut SimpleCommand {
+        self.options.success.exit_codes.extend(codes);
+        self
+    }
+
+    /// Decides whether the command succeeded from its status, stdout and stderr instead of its
+    /// return value.
+    ///
+    /// ```no_run
+    /// use simple_command::SimpleCommand;
+    ///
+    /// // grep returns 1 when nothing matches, which is fine, but 2 on errors
+    /// SimpleCommand::new("grep")
+    ///     .args(&["-r", "TODO", "src"])
+    ///     .success_when(|status, _stdout, _stderr| matches!(status.code(), Some(0) | Some(1)))
+    ///     .run();
+    /// ```
+    pub fn success_when<F>(&mut self, predicate: F) -> &mut SimpleCommand
+        where F: Fn(ExitStatus, &[u8], &[u8]) -> bool + Send + Sync + 'static
+    {
+        self.options.success.predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Fails the command if its stderr contains `pattern`, even when it exited successfully.
+    ///
+    /// This catches tools that report errors such as `error:` without setting their return value.
+    pub fn fail_on_stderr<S: Into<String>>(&mut self, pattern: S) -> &mut SimpleCommand {
+        self.options.success.stderr_failures.push(pattern.into());
+        self
+    }
+
+    /// Reruns the command when it fails as described by `retry`.
+    ///
+    /// If every attempt fails the failure includes the output of each of them.
+    pub fn retry(&mut self, retry: &Retry) -> &mut SimpleCommand {
+        self.retry = Some(retry.clone());
+        self
+    }
+
+    /// Runs the command, panicking if anything goes wrong.
+    pub fn run(&mut self) -> Output {
+        match self.try_run() {
+            Ok(output) => output,
+            Err(err) => panic!("\n{}", err)
+        }
+    }
+
+    /// Runs the command, returning any failure instead of panicking.
+    pub fn try_run(&mut self) -> Result<Output, SimpleCommandError> {
+        let display = match &self.cmd {
+            Some(cmd) => cmd.clone(),
+            None => {
+                let mut display = self.commands.iter().map(run::describe).collect::<Vec<_>>().join(" | ");
+                for (operator, redirect) in [(">", &self.options.stdout), ("2>", &self.options.stderr)] {
+                    match redirect {
+                        Some(Redirect::Truncate(path)) => display += &format!(" {} {}", operator, quote(&path.to_string_lossy())),
+                        Some(Redirect::Append(path)) => display += &format!(" {}> {}", operator, quote(&path.to_string_lossy())),
+                        None => {}
+                    }
+                }
+                display
+            }
+        };
+        // Emitted once up front rather than for every retried attempt
+        cargo::emit_rerun_directives(&self.options.inputs, &self.options.env_inputs);
+        let (commands, options) = (&mut self.commands, &self.options);
+        match &self.retry {
+            Some(retry) => retry::run(retry, &display, || run::run(commands, &display, options)),
+            None => run::run(commands, &display, options),
+        }
+    }
+
+    fn last(&mut self) -> &mut Command {
+        self.commands.last_mut().unwrap()
+    }
+}
@@ -0,0 +1,97 @@
+//! Integration with the directives cargo reads from the stdout of a build script.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::output::{Chunk, Stream};
+
+/// Which lines of stderr to forward as `cargo:warning=` directives.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CargoWarnings {
+    /// Do not forward anything.
+    #[default]
+    None,
+    /// Forward every non-empty line of stderr.
+    All,
+    /// Forward lines of stderr containing the given string, e.g. `"warning:"`.
+    Matching(String),
+}
+
+/// Emits `cargo:warning=` directives for complete stderr lines as they arrive.
+pub(crate) struct WarningForwarder<'a> {
+    warnings: &'a CargoWarnings,
+    line: Vec<u8>,
+}
+
+impl<'a> WarningForwarder<'a> {
+    pub fn new(warnings: &'a CargoWarnings) -> WarningForwarder<'a> {
+        WarningForwarder { warnings, line: Vec::new() }
+    }
+
+    pub fn push(&mut self, chunk: &Chunk) {
+        if *self.warnings == CargoWarnings::None || chunk.stream != Stream::Stderr {
+            return;
+        }
+
+        for &byte in &chunk.data {
+            if byte == b'\n' {
+                self.emit();
+            }
+            else {
+                self.line.push(byte);
+            }
+        }
+    }
+
+    /// Emits the final line if the command did not end its output with a newline.
+    pub fn finish(mut self) {
+        self.emit();
+    }
+
+    fn emit(&mut self) {
+        let line = String::from_utf8_lossy(&self.line);
+        let line = line.trim_end_matches('\r');
+        let forward = match self.warnings {
+            CargoWarnings::None => false,
+            CargoWarnings::All => !line.trim().is_empty(),
+            CargoWarnings::Matching(pattern) => line.contains(pattern.as_str()),
+        };
+        if forward {
+            println!("cargo:warning={}", line);
+        }
+        self.line.clear();
+    }
+}
+
+/// Emits `cargo:rerun-if-changed` for each input and `cargo:rerun-if-env-changed` for each
+/// environment variable.
+///
+/// Directories are expanded recursively so that every file inside them is tracked.
+/// Symlinks are tracked themselves, but a symlinked directory is not descended into so that a
+/// link back to a parent cannot recurse forever.
+pub(crate) fn emit_rerun_directives(inputs: &[PathBuf], env_inputs: &[OsString]) {
+    for input in inputs {
+        rerun_if_changed(input);
+    }
+    for var in env_inputs {
+        println!("cargo:rerun-if-env-changed={}", var.to_string_lossy());
+    }
+}
+
+fn rerun_if_changed(path: &Path) {
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir()) {
+        let mut entries: Vec<_> = match fs::read_dir(path) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+            // The directory itself is still tracked, which is the best we can do.
+            Err(_) => return,
+        };
+        // Sorted so that the build script output is deterministic
+        entries.sort();
+        for entry in entries {
+            rerun_if_changed(&entry);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::output::Output;
+use crate::parse::ParseError;
+use crate::script::StepOutcome;
+use crate::signal;
+use crate::version::Version;
+
+/// Everything that can go wrong when running a command.
+///
+/// Formatting with `{:#}` prefixes each line of the command's output with `[out]` or `[err]`.
+/// New variants may be added as the crate grows, so matches need a wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SimpleCommandError {
+    /// The command string contained no words.
+    NoCommand,
+    /// The command string could not be split into words.
+    Parse(ParseError),
+    /// The command string uses an environment variable that is not set and has no default.
+    MissingEnvVar { cmd: String, name: String },
+    /// A glob pattern in the command string did not match any paths.
+    NoGlobMatch { cmd: String, pattern: String },
+    /// The program does not exist.
+    ///
+    /// `searched` is the `PATH` that was searched, `not_executable` are files with the name of the
+    /// program that lack execute permission and `suggestions` are programs with similar names.
+    NotFound {
+        cmd: String,
+        program: String,
+        searched: Vec<PathBuf>,
+        not_executable: Vec<PathBuf>,
+        suggestions: Vec<String>,
+    },
+    /// The program exists but could not be started.
+    SpawnFailed { cmd: String, source: io::Error },
+    /// A file used as stdin or as the target of a redirect could not be accessed.
+    File { cmd: String, path: PathBuf, source: io::Error },
+    /// Reading the output of, or waiting on, the command failed.
+    IoError { cmd: String, source: io::Error },
+    /// The command exited with a return value that is not accepted.
+    ///
+    /// `stage` is the index of the command of the pipeline that failed.
+    NonZeroExit { cmd: String, code: i32, stage: usize, output: Output },
+    /// The command was terminated without a return value.
+    ///
+    /// `stage` is the index of the command of the pipeline that was killed.
+    /// On Unix `signal` is the signal that killed it, see `signal_name` to tell e.g. `SIGKILL`
+    /// from the out of memory killer apart from a `SIGSEGV` crash.
+    KilledBySignal {
+        cmd: String,
+        status: ExitStatus,
+        stage: usize,
+        signal: Option<i32>,
+        core_dumped: bool,
+        output: Output,
+    },
+    /// The command finished but was considered a failure by its success check, or wrote text to
+    /// stderr that marks it as failed.
+    Rejected { cmd: String, reason: String, output: Output },
+    /// The command did not finish within its timeout and was killed.
+    TimedOut { cmd: String, timeout: Duration, output: Output },
+    /// A step of a script separated by `&&`, `||` or `;` failed.
+    ///
+    /// `steps` contains every step that ran, with `step` being the index of the one that failed.
+    ScriptFailed { cmd: String, step: usize, steps: Vec<StepOutcome> },
+    /// A `cd` or `export` builtin of a script was given invalid arguments.
+    Builtin { cmd: String, message: String },
+    /// A script file could not be read.
+    ReadScript { path: PathBuf, source: io::Error },
+    /// A line of a script file failed, `line` is the 1-based line number it starts on.
+    ScriptLine { script: String, line: usize, source: Box<SimpleCommandError> },
+    /// Some of the `total` commands of a batch failed, `failures` are in the order the commands
+    /// were added.
+    BatchFailed { total: usize, failures: Vec<SimpleCommandError> },
+    /// The command failed on every attempt allowed by its `Retry`, or with a failure that is not
+    /// retried after an earlier attempt was.
+    RetriesFailed { cmd: String, attempts: Vec<SimpleCommandError> },
+    /// The output of a version command did not contain a version.
+    VersionNotFound { cmd: String, output: Output },
+    /// A program is older than the version the build needs.
+    VersionTooOld { program: String, found: Version, minimum: Version },
+}
+
+impl fmt::Display for SimpleCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimpleCommandError::NoCommand => write!(f, "No command specified"),
+            SimpleCommandError::Parse(err) => write!(f, "{}", err),
+            SimpleCommandError::MissingEnvVar { cmd, name } => {
+                write!(f, "Environment variable {} used in command \"{}\" is not set", name, cmd)
+            }
+            SimpleCommandError::NoGlobMatch { cmd, pattern } => {
+                write!(f, "Pattern {} used in command \"{}\" did not match any paths", pattern, cmd)
+            }
+            SimpleCommandError::NotFound { cmd, program, searched, not_executable, suggestions } => {
+                write!(f, "Command \"{}\" not found", cmd)?;
+                if !searched.is_empty() {
+                    write!(f, "\nProgram \"{}\" is not in any directory of PATH:", program)?;
+                    for dir in searched {
+                        write!(f, "\n    {}", dir.display())?;
+                    }
+                }
+                for path in not_executable {
+                    write!(f, "\n{} exists but is not executable", path.display())?;
+                }
+                if !suggestions.is_empty() {
+                    write!(f, "\nDid you mean: {}", suggestions.join(", "))?;
+                }
+                Ok(())
+            }
+            SimpleCommandError::SpawnFailed { cmd, source } => {
+                write!(f, "Command \"{}\" failed to start: {}", cmd, source)
+            }
+            SimpleCommandError::File { cmd, path, source } => {
+                write!(f, "Failed to access {} for command \"{}\": {}", path.display(), cmd, source)
+            }
+            SimpleCommandError::IoError { cmd, source } => {
+                write!(f, "Failed to read output of command \"{}\": {}", cmd, source)
+            }
+            SimpleCommandError::NonZeroExit { cmd, code, stage, output } => {
+                writeln!(f, "Command \"{}\" failed with return value {}", cmd, code)?;
+                write_failed_stage(f, output, *stage)?;
+                write!(f, "{}", output.transcript(f.alternate()))
+            }
+            SimpleCommandError::KilledBySignal { cmd, status, stage, output, .. } => {
+                writeln!(f, "Command \"{}\" {}", cmd, signal::describe_failure(*status))?;
+                write_failed_stage(f, output, *stage)?;
+                write!(f, "{}", output.transcript(f.alternate()))
+            }
+            SimpleCommandError::Rejected { cmd, reason, output } => {
+                write!(f, "Command \"{}\" {}\n{}", cmd, reason, output.transcript(f.alternate()))
+            }
+            SimpleCommandError::TimedOut { cmd, timeout, output } => {
+                write!(f, "Command \"{}\" timed out after {:?}\n{}", cmd, timeout, output.transcript(f.alternate()))
+            }
+            SimpleCommandError::ScriptFailed { cmd, step, steps } => {
+                writeln!(f, "Script \"{}\" failed at \"{}\"", cmd, steps[*step].cmd)?;
+                for outcome in steps {
+                    let report = match &outcome.result {
+                        Ok(output) => {
+                            writeln!(f, "\nStep \"{}\" succeeded", outcome.cmd)?;
+                            output.transcript(f.alternate())
+                        }
+                        Err(err) => {
+                            writeln!(f, "\nStep \"{}\" failed", outcome.cmd)?;
+                            if f.alternate() { format!("{:#}", err) } else { err.to_string() }
+                        }
+                    };
+                    write!(f, "{}", report)?;
+                    if !report.is_empty() && !report.ends_with('\n') {
+                        writeln!(f)?;
+                    }
+                }
+                Ok(())
+            }
+            SimpleCommandError::Builtin { cmd, message } => write!(f, "Command \"{}\" failed: {}", cmd, message),
+            SimpleCommandError::ReadScript { path, source } => {
+                write!(f, "Failed to read script {}: {}", path.display(), source)
+            }
+            SimpleCommandError::ScriptLine { script, line, source } => {
+                if f.alternate() {
+                    write!(f, "{}:{}: {:#}", script, line, source)
+                }
+                else {
+                    write!(f, "{}:{}: {}", script, line, source)
+                }
+            }
+            SimpleCommandError::BatchFailed { total, failures } => {
+                write!(f, "{} of {} commands failed", failures.len(), total)?;
+                for err in failures {
+                    let report = if f.alternate() { format!("{:#}", err) } else { err.to_string() };
+                    write!(f, "\n\n{}", report.trim_end())?;
+                }
+                Ok(())
+            }
+            SimpleCommandError::RetriesFailed { cmd, attempts } => {
+                write!(f, "Command \"{}\" failed after {} attempts", cmd, attempts.len())?;
+                for (i, err) in attempts.iter().enumerate() {
+                    let report = if f.alternate() { format!("{:#}", err) } else { err.to_string() };
+                    write!(f, "\n\nAttempt {} failed\n{}", i + 1, report.trim_end())?;
+                }
+                Ok(())
+            }
+            SimpleCommandError::VersionNotFound { cmd, output } => {
+                write!(f, "Could not find a version in the output of \"{}\"\n{}", cmd, output.transcript(f.alternate()))
+            }
+            SimpleCommandError::VersionTooOld { program, found, minimum } => {
+                write!(f, "Program \"{}\" is too old, found {}, need >= {}", program, found, minimum)
+            }
+        }
+    }
+}
+
+/// Identifies which command of a pipeline caused the failure.
+fn write_failed_stage(f: &mut fmt::Formatter, output: &Output, stage: usize) -> fmt::Result {
+    if output.stages().len() > 1 {
+        let failed = &output.stages()[stage];
+        writeln!(f, "Stage {} \"{}\" {}", stage + 1, failed.cmd, signal::describe_failure(failed.status))?;
+    }
+    Ok(())
+}
+
+impl Error for SimpleCommandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SimpleCommandError::Parse(err) => Some(err),
+            SimpleCommandError::SpawnFailed { source, .. } => Some(source),
+            SimpleCommandError::File { source, .. } => Some(source),
+            SimpleCommandError::IoError { source, .. } => Some(source),
+            SimpleCommandError::ReadScript { source, .. } => Some(source),
+            SimpleCommandError::ScriptLine { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<ParseError> for SimpleCommandError {
+    fn from(err: ParseError) -> Self {
+        SimpleCommandError::Parse(err)
+    }
+}
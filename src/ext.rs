@@ -0,0 +1,40 @@
+use std::process::Command;
+
+use crate::error::SimpleCommandError;
+use crate::output::Output;
+use crate::run::{self, Options};
+
+/// Runs an already configured `std::process::Command` like `simple_command`.
+///
+/// This is useful when the command comes from another crate such as `cc` or `cmake`.
+/// The failure message includes the program, arguments, environment and working directory
+/// of the command.
+/// Stdin is left as configured on the command rather than being emptied.
+///
+/// ```no_run
+/// use std::process::Command;
+/// use simple_command::CommandExt;
+///
+/// Command::new("make").arg("-C").arg("vendor").env("CC", "clang").run_simple();
+/// ```
+pub trait CommandExt {
+    /// Runs the command, panicking if anything goes wrong.
+    fn run_simple(&mut self) -> Output;
+
+    /// Runs the command, returning any failure instead of panicking.
+    fn try_run_simple(&mut self) -> Result<Output, SimpleCommandError>;
+}
+
+impl CommandExt for Command {
+    fn run_simple(&mut self) -> Output {
+        match self.try_run_simple() {
+            Ok(output) => output,
+            Err(err) => panic!("\n{}", err)
+        }
+    }
+
+    fn try_run_simple(&mut self) -> Result<Output, SimpleCommandError> {
+        let display = run::describe(self);
+        run::run(std::slice::from_mut(self), &display, &Options::default())
+    }
+}
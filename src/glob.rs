@@ -0,0 +1,389 @@
+//! Expands unquoted `*`, `?`, `**` and `[...]` patterns in words into matching paths.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::parse::{Part, Word};
+use crate::script::Shell;
+
+/// How unquoted glob patterns in a command string are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Glob {
+    /// Patterns are passed to the command as written.
+    #[default]
+    Disabled,
+    /// Patterns are replaced with the sorted paths they match, a pattern matching nothing is an error.
+    Enabled,
+    /// Like `Enabled`, except a pattern matching nothing is removed.
+    NullGlob,
+}
+
+/// A character of a pattern, `special` if it was unquoted and so can act as a wildcard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PatternChar {
+    c: char,
+    special: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Matcher {
+    Literal(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[a-z_]` or the `negated` `[!a-z_]`
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// Returns true if the word contains an unquoted `*`, `?` or complete `[...]` class.
+///
+/// A `[` without a closing `]`, as in `[ -d /tmp ]`, is an ordinary character.
+pub(crate) fn is_glob(word: &Word) -> bool {
+    let mut pattern = Vec::new();
+    for part in &word.parts {
+        match part {
+            Part::Text { text, quoted } => pattern.extend(text.chars().map(|c| PatternChar { c, special: !quoted })),
+            Part::Var { raw, .. } => pattern.extend(raw.chars().map(|c| PatternChar { c, special: false })),
+        }
+    }
+    pattern.split(|c| c.c == '/').any(|component| !is_literal(&compile(component)))
+}
+
+/// Expands the word into the sorted list of paths it matches.
+///
+/// Relative patterns are matched from the script's working directory, and the paths are returned
+/// relative to it.
+/// Returns the name of the first variable in the word that is unset and has no default.
+pub(crate) fn expand(word: &Word, shell: &Shell) -> Result<Vec<OsString>, String> {
+    let mut pattern = Vec::new();
+    for part in &word.parts {
+        match part {
+            Part::Text { text, quoted } => pattern.extend(text.chars().map(|c| PatternChar { c, special: !quoted })),
+            // The values of variables never act as wildcards
+            Part::Var { .. } => {
+                let value = Word { parts: vec![part.clone()] }.expand(shell)?;
+                pattern.extend(value.to_string_lossy().chars().map(|c| PatternChar { c, special: false }));
+            }
+        }
+    }
+
+    let mut components = pattern.split(|c| c.c == '/');
+    let mut matches = vec![String::new()];
+    if pattern.first().is_some_and(|c| c.c == '/') {
+        components.next();
+        matches = vec!["/".to_string()];
+    }
+
+    let mut components = components.peekable();
+    while let Some(component) = components.next() {
+        if component.is_empty() {
+            continue;
+        }
+
+        let mut next = Vec::new();
+        if component.len() == 2 && component.iter().all(|c| c.c == '*' && c.special) {
+            // `**` matches any number of directories, including none, and as the last component
+            // it matches the files within them as well
+            let files = components.peek().is_none();
+            for path in matches {
+                descendants(shell, &path, files, &mut next);
+                next.push(path);
+            }
+        }
+        else if is_literal(&compile(component)) {
+            let name: String = component.iter().map(|c| c.c).collect();
+            for path in matches {
+                let path = join(&path, &name);
+                if resolve(shell, &path).symlink_metadata().is_ok() {
+                    next.push(path);
+                }
+            }
+        }
+        else {
+            let matchers = compile(component);
+            // Like a shell, hidden files are only matched when the pattern starts with a literal `.`
+            let hidden = component[0].c == '.';
+            for path in matches {
+                for name in entries(shell, &path) {
+                    if (hidden || !name.starts_with('.')) && matches_name(&matchers, &name.chars().collect::<Vec<_>>()) {
+                        next.push(join(&path, &name));
+                    }
+                }
+            }
+        }
+        matches = next;
+    }
+
+    // Sorted so that builds are reproducible regardless of directory order
+    matches.sort();
+    matches.dedup();
+    Ok(matches.into_iter().filter(|path| !path.is_empty()).map(OsString::from).collect())
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() || path.ends_with('/') {
+        format!("{}{}", path, name)
+    }
+    else {
+        format!("{}/{}", path, name)
+    }
+}
+
+/// The path as seen from this process rather than from the script's working directory.
+fn resolve(shell: &Shell, path: &str) -> PathBuf {
+    let path = if path.is_empty() { Path::new(".") } else { Path::new(path) };
+    shell.path(path)
+}
+
+/// The names of the entries in the directory, which is the working directory if `path` is empty.
+fn entries(shell: &Shell, path: &str) -> Vec<String> {
+    match fs::read_dir(resolve(shell, path)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Pushes every non-hidden directory below `path` onto `result`, along with every other
+/// non-hidden entry if `files` is set.
+///
+/// Symlinks to directories are not followed, as a link back to a parent would recurse forever.
+fn descendants(shell: &Shell, path: &str, files: bool, result: &mut Vec<String>) {
+    for name in entries(shell, path) {
+        if name.starts_with('.') {
+            continue;
+        }
+        let child = join(path, &name);
+        let is_dir = resolve(shell, &child).symlink_metadata().is_ok_and(|metadata| metadata.is_dir());
+        if is_dir {
+            descendants(shell, &child, files, result);
+        }
+        if is_dir || files {
+            result.push(child);
+        }
+    }
+}
+
+fn compile(component: &[PatternChar]) -> Vec<Matcher> {
+    let mut matchers = Vec::new();
+    let mut i = 0;
+    while i < component.len() {
+        let PatternChar { c, special } = component[i];
+        i += 1;
+        match c {
+            '*' if special => matchers.push(Matcher::Star),
+            '?' if special => matchers.push(Matcher::Any),
+            '[' if special => match compile_class(&component[i..]) {
+                Some((class, len)) => {
+                    matchers.push(class);
+                    i += len;
+                }
+                // Without a closing `]` the `[` is just a character
+                None => matchers.push(Matcher::Literal('[')),
+            }
+            c => matchers.push(Matcher::Literal(c)),
+        }
+    }
+    matchers
+}
+
+fn is_literal(matchers: &[Matcher]) -> bool {
+    matchers.iter().all(|matcher| matches!(matcher, Matcher::Literal(_)))
+}
+
+/// Compiles the contents of a `[...]` class, returning it along with how many characters it used
+/// including the closing `]`.
+fn compile_class(class: &[PatternChar]) -> Option<(Matcher, usize)> {
+    let mut i = 0;
+    let negated = class.first().is_some_and(|c| c.special && (c.c == '!' || c.c == '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    // A `]` straight after the opening `[` is part of the class rather than closing it
+    let mut first = true;
+    while i < class.len() {
+        let c = class[i].c;
+        if c == ']' && !first {
+            return Some((Matcher::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+
+        if class.get(i + 1).is_some_and(|c| c.c == '-') && class.get(i + 2).is_some_and(|c| c.c != ']') {
+            ranges.push((c, class[i + 2].c));
+            i += 3;
+        }
+        else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+fn matches_name(matchers: &[Matcher], name: &[char]) -> bool {
+    match matchers.split_first() {
+        None => name.is_empty(),
+        Some((Matcher::Star, rest)) => (0..=name.len()).any(|i| matches_name(rest, &name[i..])),
+        Some((matcher, rest)) => match name.split_first() {
+            Some((&c, name)) => {
+                let matched = match matcher {
+                    Matcher::Literal(literal) => c == *literal,
+                    Matcher::Any => true,
+                    Matcher::Class { negated, ranges } => {
+                        ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+                    }
+                    Matcher::Star => unreachable!(),
+                };
+                matched && matches_name(rest, name)
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::SimpleCommand;
+    use crate::error::SimpleCommandError;
+    use crate::parse::{quote, tokenize, Token};
+
+    /// A fresh directory containing `files`, with any parent directories created.
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("simple_command_glob_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn word(pattern: &str) -> Word {
+        match tokenize(pattern).unwrap().remove(0).0 {
+            Token::Word(word) => word,
+            token => panic!("not a word: {:?}", token),
+        }
+    }
+
+    fn glob(dir: &Path, pattern: &str) -> Vec<String> {
+        let shell = Shell { cwd: Some(dir.to_path_buf()), ..Shell::default() };
+        let word = word(pattern);
+        assert!(is_glob(&word), "{}", pattern);
+        expand(&word, &shell).unwrap().into_iter().map(|path| path.into_string().unwrap()).collect()
+    }
+
+    #[test]
+    fn wildcards() {
+        let dir = tree("wildcards", &["a.rs", "b.rs", "ab.rs", "c.txt", "src/d.rs"]);
+        assert_eq!(glob(&dir, "*.rs"), ["a.rs", "ab.rs", "b.rs"]);
+        assert_eq!(glob(&dir, "?.rs"), ["a.rs", "b.rs"]);
+        assert_eq!(glob(&dir, "*/*.rs"), ["src/d.rs"]);
+        assert_eq!(glob(&dir, "*.none"), Vec::<String>::new());
+        // Quoted wildcards are literal characters
+        assert_eq!(glob(&dir, "'a'*.rs"), ["a.rs", "ab.rs"]);
+        assert!(!is_glob(&word("'*.rs'")));
+        assert!(!is_glob(&word("\\*.rs")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn classes() {
+        let dir = tree("classes", &["a1", "b2", "c3", "-", "]"]);
+        assert_eq!(glob(&dir, "[ab]?"), ["a1", "b2"]);
+        assert_eq!(glob(&dir, "?[2-9]"), ["b2", "c3"]);
+        assert_eq!(glob(&dir, "[!a]?"), ["b2", "c3"]);
+        assert_eq!(glob(&dir, "[^ab]?"), ["c3"]);
+        assert_eq!(glob(&dir, "[]-]"), ["-", "]"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unclosed_bracket_is_not_a_glob() {
+        assert!(!is_glob(&word("[")));
+        assert!(!is_glob(&word("a[b")));
+        assert!(!is_glob(&word("[a/b]")));
+        assert!(is_glob(&word("[a]")));
+
+        let output = SimpleCommand::parse_with_glob("echo [ -d / ]", Glob::Enabled).unwrap().run();
+        assert_eq!(output.stdout_lossy(), "[ -d / ]\n");
+    }
+
+    #[test]
+    fn recursive() {
+        let dir = tree("recursive", &["a.rs", "src/b.rs", "src/c/d.rs", "src/c/e.txt", ".git/f.rs"]);
+        assert_eq!(glob(&dir, "**/*.rs"), ["a.rs", "src/b.rs", "src/c/d.rs"]);
+        assert_eq!(glob(&dir, "src/**/*.rs"), ["src/b.rs", "src/c/d.rs"]);
+        assert_eq!(glob(&dir, "**"), ["a.rs", "src", "src/b.rs", "src/c", "src/c/d.rs", "src/c/e.txt"]);
+        assert_eq!(glob(&dir, "src/**"), ["src", "src/b.rs", "src/c", "src/c/d.rs", "src/c/e.txt"]);
+        assert_eq!(glob(&dir, "**/"), ["src", "src/c"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recursive_does_not_follow_symlinks() {
+        let dir = tree("symlinks", &["src/a.rs"]);
+        std::os::unix::fs::symlink(&dir, dir.join("src/loop")).unwrap();
+        assert_eq!(glob(&dir, "**/*.rs"), ["src/a.rs"]);
+        // The link itself still matches a pattern
+        assert_eq!(glob(&dir, "src/l*"), ["src/loop"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn hidden_files() {
+        let dir = tree("hidden", &[".hidden", "shown"]);
+        assert_eq!(glob(&dir, "*"), ["shown"]);
+        assert_eq!(glob(&dir, "?hidden"), Vec::<String>::new());
+        assert_eq!(glob(&dir, ".*"), [".hidden"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn absolute_patterns_and_sorting() {
+        let dir = tree("sorting", &["b", "c", "a", "B"]);
+        let prefix = dir.to_str().unwrap();
+        let expected: Vec<String> = ["B", "a", "b", "c"].iter().map(|name| format!("{}/{}", prefix, name)).collect();
+        assert_eq!(glob(Path::new("/"), &format!("{}/*", quote(prefix))), expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scripts_and_batches() {
+        let dir = tree("scripts", &["a.rs", "b.rs"]);
+        let dir_word = quote(dir.to_str().unwrap());
+        let output = crate::simple_command_with_glob(&format!("cd {} && echo *.rs", dir_word), Glob::Enabled);
+        assert_eq!(output.stdout_lossy(), "a.rs b.rs\n");
+
+        let outputs = crate::Batch::new()
+            .glob(Glob::Enabled)
+            .add(&format!("ls {}/*.rs", dir_word))
+            .run();
+        assert_eq!(outputs[0].stdout_lossy().lines().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn no_match() {
+        let dir = tree("no_match", &["a"]);
+        let cmd = format!("echo {}/*.none b", quote(dir.to_str().unwrap()));
+        let output = SimpleCommand::parse_with_glob(&cmd, Glob::NullGlob).unwrap().run();
+        assert_eq!(output.stdout_lossy(), "b\n");
+        match SimpleCommand::parse_with_glob(&cmd, Glob::Enabled) {
+            Err(SimpleCommandError::NoGlobMatch { pattern, .. }) => assert!(pattern.ends_with("/*.none"), "{}", pattern),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // Patterns are left alone unless enabled
+        let output = SimpleCommand::parse_with_glob(&cmd, Glob::Disabled).unwrap().run();
+        assert!(output.stdout_lossy().ends_with("/*.none b\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+//! A client for the GNU make jobserver that cargo shares with build scripts through `CARGO_MAKEFLAGS`.
+//!
+//! Commands are given the jobserver so that `make` and `ninja` run within cargo's job limit rather
+//! than starting their own jobs on every CPU, and `Batch` takes a token for each extra command it
+//! runs at once.
+
+use std::env;
+use std::ffi::OsString;
+use std::process::Command;
+use std::sync::OnceLock;
+#[cfg(unix)]
+use std::fs::{File, OpenOptions};
+#[cfg(unix)]
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::mem::ManuallyDrop;
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use std::time::Duration;
+
+#[cfg(unix)]
+mod sys {
+    pub const F_GETFD: i32 = 1;
+    pub const F_SETFD: i32 = 2;
+    pub const FD_CLOEXEC: i32 = 1;
+
+    extern "C" {
+        pub fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+    }
+}
+
+pub(crate) struct Jobserver {
+    /// The flags cargo provided, passed on to commands as `MAKEFLAGS`.
+    makeflags: OsString,
+    #[cfg(unix)]
+    pipe: Option<Pipe>,
+}
+
+/// Where the jobserver's tokens live according to `--jobserver-auth=`.
+#[cfg(unix)]
+#[derive(Debug, PartialEq, Eq)]
+enum Auth<'a> {
+    /// `R,W`, descriptors of a pipe inherited from cargo.
+    Fds(i32, i32),
+    /// `fifo:PATH`, a named pipe.
+    Fifo(&'a str),
+}
+
+/// The pipe or fifo tokens are read from and written back to.
+#[cfg(unix)]
+struct Pipe {
+    // Never closed as the descriptors of a pipe belong to the whole process.
+    read: ManuallyDrop<File>,
+    write: ManuallyDrop<File>,
+    /// The descriptors of an inherited pipe, which must stay open in commands.
+    /// `None` for a fifo which commands open by path.
+    fds: Option<(i32, i32)>,
+}
+
+/// A token acquired from the jobserver, returned when dropped.
+pub(crate) struct Token {
+    #[cfg(unix)]
+    byte: u8,
+}
+
+/// The jobserver cargo passed to the build script, if any.
+pub(crate) fn get() -> Option<&'static Jobserver> {
+    static JOBSERVER: OnceLock<Option<Jobserver>> = OnceLock::new();
+    JOBSERVER.get_or_init(Jobserver::from_env).as_ref()
+}
+
+/// Gives the command access to the jobserver, unless it sets `MAKEFLAGS` itself.
+pub(crate) fn configure(command: &mut Command) {
+    let jobserver = match get() {
+        Some(jobserver) => jobserver,
+        None => return,
+    };
+    if command.get_envs().any(|(key, _)| key == "MAKEFLAGS") {
+        return;
+    }
+    command.env("MAKEFLAGS", &jobserver.makeflags);
+
+    #[cfg(unix)]
+    if let Some((read, write)) = jobserver.pipe.as_ref().and_then(|pipe| pipe.fds) {
+        use std::os::unix::process::CommandExt;
+        // SAFETY: fcntl is async-signal-safe
+        unsafe {
+            command.pre_exec(move || {
+                for fd in [read, write] {
+                    let flags = sys::fcntl(fd, sys::F_GETFD);
+                    if flags == -1 || sys::fcntl(fd, sys::F_SETFD, flags & !sys::FD_CLOEXEC) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+impl Jobserver {
+    fn from_env() -> Option<Jobserver> {
+        let makeflags = env::var_os("CARGO_MAKEFLAGS")?;
+        #[cfg(unix)]
+        let pipe = makeflags.to_str().and_then(Pipe::from_makeflags);
+        // Commands must not be pointed at descriptors that turned out not to be the jobserver
+        #[cfg(unix)]
+        let makeflags = match (&pipe, makeflags.to_str()) {
+            (None, Some(flags)) => without_auth(flags).into(),
+            _ => makeflags,
+        };
+        Some(Jobserver {
+            makeflags,
+            #[cfg(unix)]
+            pipe,
+        })
+    }
+
+    /// Blocks until a token is available.
+    ///
+    /// Returns `None` if there is no usable jobserver, in which case the caller carries on without a token.
+    pub fn acquire(&self) -> Option<Token> {
+        #[cfg(unix)]
+        {
+            let pipe = self.pipe.as_ref()?;
+            let mut byte = [0];
+            loop {
+                match (&*pipe.read).read(&mut byte) {
+                    Ok(1) => return Some(Token { byte: byte[0] }),
+                    Ok(_) => return None,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    // Newer versions of make may hand out a non-blocking pipe
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(10)),
+                    Err(_) => return None,
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        None
+    }
+}
+
+#[cfg(unix)]
+impl Pipe {
+    /// Opens the jobserver described by `makeflags`, if it is usable.
+    fn from_makeflags(makeflags: &str) -> Option<Pipe> {
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::io::FromRawFd;
+
+        let pipe = match parse_auth(makeflags)? {
+            Auth::Fifo(path) => {
+                let fifo = OpenOptions::new().read(true).write(true).open(path).ok()?;
+                let write = fifo.try_clone().ok()?;
+                Pipe { read: ManuallyDrop::new(fifo), write: ManuallyDrop::new(write), fds: None }
+            }
+            Auth::Fds(read, write) => {
+                // Cargo does not guarantee the descriptors are still open, e.g. if the build script
+                // was not started directly by cargo
+                // SAFETY: F_GETFD only checks the descriptor
+                if [read, write].iter().any(|&fd| fd < 0 || unsafe { sys::fcntl(fd, sys::F_GETFD) } == -1) {
+                    return None;
+                }
+                // SAFETY: the descriptors are open and are never closed through these files
+                let (read_file, write_file) = unsafe { (File::from_raw_fd(read), File::from_raw_fd(write)) };
+                Pipe { read: ManuallyDrop::new(read_file), write: ManuallyDrop::new(write_file), fds: Some((read, write)) }
+            }
+        };
+
+        // The descriptors may since have been reused for an ordinary file, which reading and
+        // writing tokens would corrupt
+        let is_fifo = |file: &File| file.metadata().is_ok_and(|metadata| metadata.file_type().is_fifo());
+        if !is_fifo(&pipe.read) || !is_fifo(&pipe.write) {
+            return None;
+        }
+        Some(pipe)
+    }
+}
+
+/// Parses the last `--jobserver-auth=` or `--jobserver-fds=` flag.
+#[cfg(unix)]
+fn parse_auth(makeflags: &str) -> Option<Auth<'_>> {
+    let auth = makeflags.split_whitespace()
+        .filter_map(|flag| flag.strip_prefix("--jobserver-auth=").or_else(|| flag.strip_prefix("--jobserver-fds=")))
+        .next_back()?;
+
+    if let Some(path) = auth.strip_prefix("fifo:") {
+        return Some(Auth::Fifo(path));
+    }
+    let (read, write) = auth.split_once(',')?;
+    Some(Auth::Fds(read.parse().ok()?, write.parse().ok()?))
+}
+
+/// `makeflags` with any `--jobserver-auth=` or `--jobserver-fds=` flags removed.
+#[cfg(unix)]
+fn without_auth(makeflags: &str) -> String {
+    makeflags.split_whitespace()
+        .filter(|flag| !flag.starts_with("--jobserver-auth=") && !flag.starts_with("--jobserver-fds="))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Drop for Token {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pipe) = get().and_then(|jobserver| jobserver.pipe.as_ref()) {
+            // A token that cannot be returned is lost, which only reduces the parallelism
+            (&*pipe.write).write_all(&[self.byte]).ok();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_auth() {
+        assert_eq!(parse_auth("-j --jobserver-fds=3,4 --jobserver-auth=5,6"), Some(Auth::Fds(5, 6)));
+        assert_eq!(parse_auth("-j4 --jobserver-fds=3,4"), Some(Auth::Fds(3, 4)));
+        assert_eq!(parse_auth("-j --jobserver-auth=fifo:/tmp/GMfifo1"), Some(Auth::Fifo("/tmp/GMfifo1")));
+        assert_eq!(parse_auth("-j --jobserver-auth=3"), None);
+        assert_eq!(parse_auth("-j --jobserver-auth=a,b"), None);
+        assert_eq!(parse_auth("-j4"), None);
+    }
+
+    #[test]
+    fn strips_auth() {
+        assert_eq!(without_auth("-j --jobserver-fds=3,4 --jobserver-auth=3,4"), "-j");
+        assert_eq!(without_auth("-j4 -- FOO=1"), "-j4 -- FOO=1");
+    }
+
+    #[test]
+    fn rejects_descriptors_that_are_not_pipes() {
+        use std::os::unix::io::AsRawFd;
+
+        let path = env::temp_dir().join(format!("simple_command_jobserver_{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let fd = file.as_raw_fd();
+        assert!(Pipe::from_makeflags(&format!("-j --jobserver-auth={},{}", fd, fd)).is_none());
+        assert!(Pipe::from_makeflags(&format!("-j --jobserver-auth=fifo:{}", path.display())).is_none());
+        drop(file);
+        std::fs::remove_file(path).unwrap();
+    }
+}
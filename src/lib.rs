@@ -4,74 +4,209 @@
 //! The `simple_command` function does exactly that, panicking if anything at all goes wrong and
 //! displaying the combined stderr and stdout.
 //!
+//! The command is split into words following POSIX shell quoting rules, so arguments containing
+//! spaces can be passed with single quotes, double quotes or backslash escapes.
+//! e.g. `simple_command("git commit -m \"two words\"")`
+//!
+//! Environment variables written as `$VAR`, `${VAR}` or `${VAR:-default}` are expanded, except within
+//! single quotes, e.g. `simple_command("protoc --rust_out=$OUT_DIR schema.proto")`.
+//! Expansion happens after the command is split into words, so a value containing spaces remains a
+//! single argument.
+//! The default of `${VAR:-default}` is used when the variable is unset or empty, e.g. `${CC:-cc}`,
+//! and may refer to other variables.
+//!
+//! Glob patterns such as `proto/*.proto` are passed through as written unless the command is run
+//! with `simple_command_with_glob` or created with `SimpleCommand::parse_with_glob`, which expand
+//! them into a sorted list of matching paths.
+//!
+//! Commands can be chained into a pipeline with `|`, e.g. `simple_command("git ls-files | xargs sha256sum")`.
+//! The pipeline fails if any of its commands fail and the failure identifies which one it was.
+//!
+//! Output can be redirected into files with `>`, `>>`, `2>` and `2>>`, e.g. `simple_command("bindgen wrapper.h > out/bindings.rs")`.
+//! The file is only replaced once the command succeeds, so a failed command never leaves behind a
+//! half written file for cargo to compile.
+//!
+//! Several commands can be combined with `&&`, `||` and `;`, e.g. `simple_command("mkdir -p out && cp a.txt out; touch done")`.
+//! These are evaluated by the crate rather than a shell. The script stops at the first failing command
+//! not handled by a following `||`, and the failure shows the output of every command that ran.
+//! This is stricter than `set -e`: `false && a; b` stops before `b`, whereas a shell would carry on.
+//! `cd dir` and `export NAME=value` are also handled by the crate and affect the commands that follow.
+//!
+//! Longer sequences of commands can be kept in a file and run with `simple_script`, one command
+//! per line with `#` comments and `\` line continuations, e.g. to share build steps with CI.
+//! A failure reports the file name and line number of the command that failed.
+//!
 //! Possible reasons for panicking include:
 //! *   No command specified
-//! *   Command does not exist
+//! *   Unterminated quote in command
+//! *   Environment variable without a default is not set
+//! *   Command does not exist, reported with the `PATH` that was searched and programs with similar names
 //! *   Non-zero return value
+//! *   Killed by a signal, reported with its name such as `SIGSEGV` or `SIGKILL` on Unix
+//! *   Timeout set with `SimpleCommand::timeout` exceeded
+//!
+//! Commands are given an empty stdin so they cannot hang waiting for input or consume the input of
+//! the build script, use `SimpleCommand::stdin` to provide input.
+//!
+//! On Unix every command runs in its own process group, so when a command fails or times out
+//! any processes it spawned are terminated along with it.
 //!
 //! DO NOT use this function in your actual application, you should be properly handling error cases!
+//!
+//! To make use of the output of a successful command, e.g. `llvm-config --libdir`, use
+//! `simple_command_output` which returns the stdout of the command.
+//! `simple_command` itself returns an `Output` which also gives access to stderr.
+//!
+//! When a command needs environment variables, a working directory or an existing argument
+//! vector, use the `SimpleCommand` builder which mirrors `std::process::Command`.
+//!
+//! A `std::process::Command` that was already configured elsewhere can be run the same way
+//! through the `CommandExt::run_simple` extension method.
+//!
+//! The output of a build script is hidden unless it fails, so `SimpleCommand::cargo_warnings`
+//! can be used to forward the stderr of a successful command as `cargo:warning=` directives.
+//!
+//! Inputs declared with `SimpleCommand::input` and `SimpleCommand::env_input` are emitted as
+//! `cargo:rerun-if-changed` and `cargo:rerun-if-env-changed` directives when the command runs.
+//!
+//! Independent commands, such as several code generators, can be run concurrently with `Batch`.
+//! Every command runs to completion and all of the failures are reported together.
+//!
+//! When cargo provides a jobserver through `CARGO_MAKEFLAGS` it is passed on to every command as
+//! `MAKEFLAGS`, so `make` and `ninja` stay within cargo's job limit, and `Batch` takes tokens
+//! from it for the commands it runs at once.
+//!
+//! Commands that fail transiently, e.g. fetching from a flaky mirror, can be rerun with
+//! `SimpleCommand::retry`, optionally only for specific return values, stderr messages or
+//! a custom check of stderr such as a regex.
+//! If every attempt fails the failure shows the output of each of them.
+//!
+//! Tools such as `diff` or `grep` give meaning to non-zero return values, these can be accepted with
+//! `SimpleCommand::success_codes` or a custom check with `SimpleCommand::success_when`.
+//! `SimpleCommand::fail_on_stderr` instead fails a command that reports `error:` but still returns 0.
+//!
+//! Builds that need a recent enough tool can check for it up front with `VersionCheck`, e.g.
+//! `VersionCheck::new("cmake").at_least(Version::new(3, 20, 0))` panics with the version that was
+//! found when it is too old.
+//!
+//! If a build script needs to recover from a failing command, e.g. to fall back to a vendored
+//! build when `pkg-config` fails, use `try_simple_command` instead which returns a
+//! `SimpleCommandError` describing what went wrong.
 
-use std::process::{Command, Stdio};
-use std::io::{BufReader, BufRead, Write};
-use std::str;
+use std::fs;
+use std::path::Path;
 
-pub fn simple_command(cmd: &str) {
-    let words: Vec<_> = cmd.split_whitespace().collect();
-    if words.len() == 0 {
-        panic!("No command specified");
-    }
+mod batch;
+mod builder;
+mod cargo;
+mod error;
+mod ext;
+mod glob;
+mod jobserver;
+mod output;
+mod parse;
+mod process;
+mod redirect;
+mod resolve;
+mod retry;
+mod run;
+mod script;
+mod signal;
+mod success;
+mod version;
 
-    let mut command = Command::new(words[0]);
-    for word in &words[1..] {
-        command.arg(word);
-    }
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::piped());
+pub use crate::batch::Batch;
+pub use crate::builder::SimpleCommand;
+pub use crate::cargo::CargoWarnings;
+pub use crate::error::SimpleCommandError;
+pub use crate::ext::CommandExt;
+pub use crate::glob::Glob;
+pub use crate::output::{Chunk, Output, Stage, Stream};
+pub use crate::parse::{quote, split, ParseError, ParseErrorKind};
+pub use crate::redirect::Redirect;
+pub use crate::retry::{Backoff, Retry};
+pub use crate::run::Stdin;
+pub use crate::script::StepOutcome;
+pub use crate::signal::signal_name;
+pub use crate::version::{Version, VersionCheck};
 
-    let mut output = Vec::new();
-    let mut child = command.spawn().unwrap();
-    {
-        let stdout = child.stdout.as_mut().expect("Wasn't stdout");
-        let stderr = child.stderr.as_mut().expect("Wasn't stderr");
+pub fn simple_command(cmd: &str) -> Output {
+    match try_simple_command(cmd) {
+        Ok(output) => output,
+        Err(err) => panic!("\n{}", err)
+    }
+}
 
-        let mut stdout = BufReader::new(stdout);
-        let mut stderr = BufReader::new(stderr);
+/// Runs the command like `simple_command` and returns its stdout.
+///
+/// The trailing newline is kept, call `trim_end` on the result when capturing a single value
+/// such as from `git rev-parse HEAD`.
+pub fn simple_command_output(cmd: &str) -> String {
+    simple_command(cmd).stdout_lossy()
+}
 
-        loop {
-            let (stdout_bytes, stderr_bytes) = match (stdout.fill_buf(), stderr.fill_buf()) {
-                (Ok(stdout), Ok(stderr)) => {
-                    output.write_all(stdout).expect("Couldn't write");
-                    output.write_all(stderr).expect("Couldn't write");
+/// Runs the command like `simple_command` but returns any failure instead of panicking.
+pub fn try_simple_command(cmd: &str) -> Result<Output, SimpleCommandError> {
+    script::run_script(cmd, Glob::Disabled)
+}
 
-                    (stdout.len(), stderr.len())
-                }
-                other => panic!("Failed to read stdout or stderr... {:?}", other)
-            };
+/// Runs the command like `simple_command`, also expanding unquoted glob patterns such as
+/// `proto/*.proto` or `src/**/*.rs` as configured by `glob`.
+///
+/// ```no_run
+/// use simple_command::{simple_command_with_glob, Glob};
+///
+/// simple_command_with_glob("protoc --rust_out=$OUT_DIR proto/*.proto", Glob::Enabled);
+/// ```
+pub fn simple_command_with_glob(cmd: &str, glob: Glob) -> Output {
+    match try_simple_command_with_glob(cmd, glob) {
+        Ok(output) => output,
+        Err(err) => panic!("\n{}", err)
+    }
+}
 
-            if stdout_bytes == 0 && stderr_bytes == 0 {
-                // Seems less-than-ideal; should be some way of
-                // telling if the child has actually exited vs just
-                // not outputting anything.
-                break;
-            }
+/// Runs the command like `simple_command_with_glob` but returns any failure instead of panicking.
+pub fn try_simple_command_with_glob(cmd: &str, glob: Glob) -> Result<Output, SimpleCommandError> {
+    script::run_script(cmd, glob)
+}
 
-            stdout.consume(stdout_bytes);
-            stderr.consume(stderr_bytes);
-        }
+/// Runs every line of a script file like `simple_command`, panicking with the file name and line
+/// number of the first line that fails.
+///
+/// `#` starts a comment, a line ending in `\` continues onto the next line, and the directory
+/// and variables set with `cd` and `export` carry over to the following lines.
+/// Relative paths in the script are relative to the working directory of the build script, not
+/// the script file.
+///
+/// ```no_run
+/// simple_command::simple_script("scripts/gen.cmds");
+/// ```
+pub fn simple_script<P: AsRef<Path>>(path: P) -> Output {
+    match try_simple_script(path) {
+        Ok(output) => output,
+        Err(err) => panic!("\n{}", err)
     }
-    let output = String::from_utf8_lossy(&output);
+}
 
-    let status = match child.wait() {
-        Ok(status) => status,
-        Err(err) => panic!("{:?}", err)
-    };
+/// Runs a script file like `simple_script` but returns any failure instead of panicking.
+pub fn try_simple_script<P: AsRef<Path>>(path: P) -> Result<Output, SimpleCommandError> {
+    let path = path.as_ref();
+    let script = fs::read_to_string(path)
+        .map_err(|source| SimpleCommandError::ReadScript { path: path.to_path_buf(), source })?;
+    script::run_script_file(&script, &path.display().to_string())
+}
 
-    if !status.success() {
-        if let Some(status) = status.code() {
-            panic!("\nCommand \"{}\" failed with return value {}\n{}", cmd, status, output);
-        }
-        else {
-            panic!("\nCommand \"{}\" failed with no return value\n{}", cmd, output);
-        }
+/// Runs a script like `simple_script` from a string rather than a file.
+///
+/// Failures refer to the script as `script`, e.g. `script:3`.
+pub fn simple_script_str(script: &str) -> Output {
+    match try_simple_script_str(script) {
+        Ok(output) => output,
+        Err(err) => panic!("\n{}", err)
     }
 }
+
+/// Runs a script like `simple_script_str` but returns any failure instead of panicking.
+pub fn try_simple_script_str(script: &str) -> Result<Output, SimpleCommandError> {
+    script::run_script_file(script, "script")
+}
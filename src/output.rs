@@ -0,0 +1,206 @@
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use crate::signal;
+
+/// The output stream a chunk of output was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn tag(self) -> &'static str {
+        match self {
+            Stream::Stdout => "[out] ",
+            Stream::Stderr => "[err] ",
+        }
+    }
+}
+
+/// A single read from one of the output streams of a command.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub stream: Stream,
+    /// Time between the command starting and this chunk being read.
+    pub elapsed: Duration,
+    pub data: Vec<u8>,
+}
+
+/// A single command of a pipeline along with how it exited.
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub cmd: String,
+    pub status: ExitStatus,
+}
+
+impl Stage {
+    /// The Unix signal that killed the command, if any.
+    pub fn signal(&self) -> Option<i32> {
+        signal::signal(self.status)
+    }
+
+    /// Whether the command dumped core when it was killed.
+    pub fn core_dumped(&self) -> bool {
+        signal::core_dumped(self.status)
+    }
+}
+
+/// The captured result of a command that ran to completion.
+///
+/// The output is stored as chunks in the order they were read, so the transcript of the command
+/// can be rendered with stdout and stderr interleaved as they happened.
+/// For a pipeline stdout is that of the last command, while stderr is collected from every command.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub(crate) stages: Vec<Stage>,
+    pub(crate) chunks: Vec<Chunk>,
+}
+
+impl Output {
+    /// The exit status of the command.
+    ///
+    /// For a pipeline this is the status of the last command to fail, or of the last command
+    /// if they all succeeded, matching `set -o pipefail`.
+    /// A script that ran no commands at all succeeded.
+    pub fn status(&self) -> ExitStatus {
+        match self.failed_stage() {
+            Some((_, stage)) => stage.status,
+            None => self.stages.last().map_or_else(ExitStatus::default, |stage| stage.status),
+        }
+    }
+
+    /// Every command of the pipeline, or just the command if it was not a pipeline.
+    ///
+    /// For a script these are the commands of the last pipeline that ran.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// The index and details of the last command in the pipeline that did not exit with 0.
+    ///
+    /// Return values accepted by `success_codes` still count here, the stage reported by an error
+    /// is the one that was not accepted.
+    pub fn failed_stage(&self) -> Option<(usize, &Stage)> {
+        self.stages.iter().enumerate().rev().find(|(_, stage)| !stage.status.success())
+    }
+
+    /// Every chunk of output in the order it was read.
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+
+    /// Everything the command wrote to stdout.
+    pub fn stdout(&self) -> Vec<u8> {
+        self.collect(Some(Stream::Stdout))
+    }
+
+    /// Everything the command wrote to stderr.
+    pub fn stderr(&self) -> Vec<u8> {
+        self.collect(Some(Stream::Stderr))
+    }
+
+    /// Everything the command wrote to stdout and stderr, in the order it was read.
+    pub fn combined(&self) -> Vec<u8> {
+        self.collect(None)
+    }
+
+    /// The stdout of the command, lossily converted to a string.
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout()).into_owned()
+    }
+
+    /// The stderr of the command, lossily converted to a string.
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr()).into_owned()
+    }
+
+    /// The combined stdout and stderr, lossily converted to a string.
+    pub fn combined_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.combined()).into_owned()
+    }
+
+    /// Renders the combined output in the order it was read.
+    ///
+    /// When `tagged` is set every line is prefixed with `[out]` or `[err]` to mark which stream it
+    /// came from.
+    pub fn transcript(&self, tagged: bool) -> String {
+        if !tagged {
+            return self.combined_lossy();
+        }
+
+        let mut transcript = String::new();
+        let mut at_line_start = true;
+        let mut chunks = self.chunks.iter().peekable();
+        while let Some(first) = chunks.next() {
+            // Join consecutive chunks from the same stream so that
+            // multi-byte characters split across reads are preserved.
+            let stream = first.stream;
+            let mut data = first.data.clone();
+            while let Some(chunk) = chunks.peek() {
+                if chunk.stream != stream {
+                    break;
+                }
+                data.extend_from_slice(&chunk.data);
+                chunks.next();
+            }
+
+            if !at_line_start {
+                transcript.push('\n');
+            }
+            for line in String::from_utf8_lossy(&data).split_inclusive('\n') {
+                transcript.push_str(stream.tag());
+                transcript.push_str(line);
+            }
+            at_line_start = transcript.ends_with('\n');
+        }
+        transcript
+    }
+
+    fn collect(&self, stream: Option<Stream>) -> Vec<u8> {
+        let mut data = Vec::new();
+        for chunk in &self.chunks {
+            if stream.is_none_or(|stream| stream == chunk.stream) {
+                data.extend_from_slice(&chunk.data);
+            }
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(chunks: &[(Stream, &[u8])]) -> Output {
+        Output {
+            stages: Vec::new(),
+            chunks: chunks.iter()
+                .map(|&(stream, data)| Chunk { stream, elapsed: Duration::ZERO, data: data.to_vec() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn transcript_tags_lines() {
+        let output = output(&[(Stream::Stdout, b"a\nb\n"), (Stream::Stderr, b"c\n"), (Stream::Stdout, b"d")]);
+        assert_eq!(output.transcript(true), "[out] a\n[out] b\n[err] c\n[out] d");
+        assert_eq!(output.transcript(false), "a\nb\nc\nd");
+    }
+
+    #[test]
+    fn transcript_breaks_line_when_stream_changes() {
+        let output = output(&[(Stream::Stdout, b"a"), (Stream::Stderr, b"b\n")]);
+        assert_eq!(output.transcript(true), "[out] a\n[err] b\n");
+        assert_eq!(output.transcript(false), "ab\n");
+    }
+
+    #[test]
+    fn transcript_joins_chunks_of_a_stream() {
+        // A line and a multi-byte character both split across reads
+        let split = output(&[(Stream::Stdout, b"ab"), (Stream::Stdout, b"c\n\xc3"), (Stream::Stdout, b"\xa9\n")]);
+        assert_eq!(split.transcript(true), "[out] abc\n[out] \u{e9}\n");
+        assert_eq!(output(&[]).transcript(true), "");
+    }
+}
@@ -0,0 +1,684 @@
+//! Splits a command string into words and operators following POSIX shell quoting rules.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::iter::{Enumerate, Peekable};
+use std::str;
+
+use crate::script::Shell;
+
+/// The reason a command string could not be split into words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    UnterminatedSingleQuote,
+    UnterminatedDoubleQuote,
+    TrailingBackslash,
+    /// An operator such as `|` or `&&` where it is not supported.
+    UnexpectedOperator,
+    /// An operator such as `|` is missing the command on one of its sides.
+    MissingCommand,
+    /// A redirect such as `>` is not followed by a file name.
+    MissingRedirectTarget,
+    /// A redirect appears before the last command of a pipeline.
+    MisplacedRedirect,
+    /// A `${` is missing its closing `}`.
+    UnterminatedVariable,
+    /// A `${...}` does not contain a valid variable name.
+    InvalidVariable,
+    /// An operator such as `&` that runs commands in the background.
+    UnsupportedOperator,
+    /// A redirect to a file descriptor such as `2>&1`, or of both streams with `&>`.
+    UnsupportedRedirect,
+}
+
+/// A command string that could not be split into words.
+///
+/// `column` is the 1-based character column of the offending quote, backslash, operator or variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub column: usize,
+    pub cmd: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = match self.kind {
+            ParseErrorKind::UnterminatedSingleQuote => "Unterminated single quote",
+            ParseErrorKind::UnterminatedDoubleQuote => "Unterminated double quote",
+            ParseErrorKind::TrailingBackslash => "Trailing backslash",
+            ParseErrorKind::UnexpectedOperator => "Unexpected operator",
+            ParseErrorKind::MissingCommand => "Missing command next to operator",
+            ParseErrorKind::MissingRedirectTarget => "Missing file name after redirect",
+            ParseErrorKind::MisplacedRedirect => "Redirects are only supported on the last command of a pipeline",
+            ParseErrorKind::UnterminatedVariable => "Unterminated variable",
+            ParseErrorKind::InvalidVariable => "Invalid variable name",
+            ParseErrorKind::UnsupportedOperator => "Running commands in the background is not supported",
+            ParseErrorKind::UnsupportedRedirect => "Redirecting to a file descriptor such as 2>&1 is not supported",
+        };
+        writeln!(f, "{} at column {}", problem, self.column)?;
+        writeln!(f, "{}", self.cmd)?;
+        write!(f, "{:>width$}", "^", width = self.column)
+    }
+}
+
+impl Error for ParseError {}
+
+/// A piece of a word, words are made up of literal text and variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Part {
+    /// `quoted` is set for text inside quotes or escaped with a backslash.
+    Text { text: String, quoted: bool },
+    /// `$NAME`, `${NAME}` or `${NAME:-default}`, `raw` is the variable as written.
+    ///
+    /// The default may itself contain variables, as in `${CC:-$HOST_CC}`.
+    Var { name: String, default: Option<Word>, raw: String },
+}
+
+/// A single word of a command before variables are expanded.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub(crate) struct Word {
+    pub parts: Vec<Part>,
+}
+
+impl Word {
+    fn push(&mut self, c: char, quoted: bool) {
+        if let Some(Part::Text { text, quoted: last_quoted }) = self.parts.last_mut() {
+            if *last_quoted == quoted {
+                text.push(c);
+                return;
+            }
+        }
+        self.parts.push(Part::Text { text: c.to_string(), quoted });
+    }
+
+    /// The word with any variables left as they were written.
+    pub fn literal(&self) -> String {
+        let mut literal = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text { text, .. } => literal.push_str(text),
+                Part::Var { raw, .. } => literal.push_str(raw),
+            }
+        }
+        literal
+    }
+
+    /// The word with its variables replaced by their values, taken from variables exported by
+    /// the script before the environment.
+    ///
+    /// Like a shell, the default of `${NAME:-default}` is used when the variable is unset or empty.
+    /// Returns the name of the first variable that is unset and has no default.
+    pub fn expand(&self, shell: &Shell) -> Result<OsString, String> {
+        let mut expanded = OsString::new();
+        for part in &self.parts {
+            match part {
+                Part::Text { text, .. } => expanded.push(text),
+                Part::Var { name, default, .. } => {
+                    let value = shell.var(name).filter(|value| default.is_none() || !value.is_empty());
+                    match (value, default) {
+                        (Some(value), _) => expanded.push(value),
+                        (None, Some(default)) => expanded.push(default.expand(shell)?),
+                        (None, None) => return Err(name.clone()),
+                    }
+                }
+            }
+        }
+        Ok(expanded)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Token {
+    Word(Word),
+    Pipe,
+    /// `>` or `>>`
+    RedirectStdout { append: bool },
+    /// `2>` or `2>>`
+    RedirectStderr { append: bool },
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `;`
+    Semicolon,
+}
+
+/// A command string broken down into the commands of its pipeline and any redirects.
+///
+/// A redirect is its target along with whether to append to it.
+#[derive(Debug)]
+pub(crate) struct Pipeline {
+    pub stages: Vec<Vec<Word>>,
+    pub stdout: Option<(Word, bool)>,
+    pub stderr: Option<(Word, bool)>,
+}
+
+/// Splits `cmd` into words the way a POSIX shell would.
+///
+/// *   Unquoted whitespace separates words.
+/// *   Single quotes preserve everything up to the closing quote.
+/// *   Double quotes preserve everything except `\` escaping `"`, `\`, `$` or `` ` ``.
+/// *   A backslash outside of quotes escapes the following character.
+/// *   `''` and `""` produce an empty word.
+///
+/// Unquoted operators such as `|` are rejected, use `SimpleCommand::parse` for command strings
+/// containing them.
+/// Environment variables are not expanded and are returned as written.
+pub fn split(cmd: &str) -> Result<Vec<String>, ParseError> {
+    let mut words = Vec::new();
+    for (token, column) in tokenize(cmd)? {
+        match token {
+            Token::Word(word) => words.push(word.literal()),
+            _ => return Err(ParseError { kind: ParseErrorKind::UnexpectedOperator, column, cmd: cmd.to_string() }),
+        }
+    }
+    Ok(words)
+}
+
+/// Splits `cmd` into the words of each command in a `|` separated pipeline, along with any
+/// redirects of the pipeline's output.
+pub(crate) fn parse_pipeline(cmd: &str) -> Result<Pipeline, ParseError> {
+    build_pipeline(tokenize(cmd)?, cmd)
+}
+
+/// How a step of a script is connected to the step before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Connector {
+    /// The first step, or a step following `;`, which always runs.
+    Always,
+    /// `&&`, runs if the previous step succeeded.
+    And,
+    /// `||`, runs if the previous step failed.
+    Or,
+}
+
+/// A single pipeline of a script along with the text it was parsed from.
+#[derive(Debug)]
+pub(crate) struct Step {
+    pub connector: Connector,
+    pub pipeline: Pipeline,
+    pub cmd: String,
+}
+
+/// Splits `cmd` into pipelines separated by `&&`, `||` and `;`.
+pub(crate) fn parse_script(cmd: &str) -> Result<Vec<Step>, ParseError> {
+    let error = |kind, column| ParseError { kind, column, cmd: cmd.to_string() };
+    let chars: Vec<char> = cmd.chars().collect();
+
+    let mut steps = Vec::new();
+    let mut connector = Connector::Always;
+    let mut tokens = Vec::new();
+    // The character index the current step starts at
+    let mut start = 0;
+    let mut operator_column = 0;
+    for (token, column) in tokenize(cmd)? {
+        let next = match token {
+            Token::And => Connector::And,
+            Token::Or => Connector::Or,
+            Token::Semicolon => Connector::Always,
+            token => {
+                tokens.push((token, column));
+                continue;
+            }
+        };
+        if tokens.is_empty() {
+            return Err(error(ParseErrorKind::MissingCommand, column));
+        }
+
+        let end = column - 1;
+        let text: String = chars[start..end].iter().collect();
+        let pipeline = build_pipeline(std::mem::take(&mut tokens), cmd)?;
+        steps.push(Step { connector, pipeline, cmd: text.trim().to_string() });
+        connector = next;
+        operator_column = column;
+        start = if next == Connector::Always { end + 1 } else { end + 2 };
+    }
+
+    if tokens.is_empty() && !steps.is_empty() {
+        // A trailing `;` is allowed, but a trailing `&&` or `||` needs a command after it
+        if connector != Connector::Always {
+            return Err(error(ParseErrorKind::MissingCommand, operator_column));
+        }
+    }
+    else {
+        let text: String = chars[start..].iter().collect();
+        let pipeline = build_pipeline(tokens, cmd)?;
+        steps.push(Step { connector, pipeline, cmd: text.trim().to_string() });
+    }
+    Ok(steps)
+}
+
+fn build_pipeline(tokens: Vec<(Token, usize)>, cmd: &str) -> Result<Pipeline, ParseError> {
+    let error = |kind, column| ParseError { kind, column, cmd: cmd.to_string() };
+
+    let mut pipeline = Pipeline { stages: vec![Vec::new()], stdout: None, stderr: None };
+    let mut pipe_column = None;
+    let mut redirect_column = None;
+    let mut tokens = tokens.into_iter();
+    while let Some((token, column)) = tokens.next() {
+        match token {
+            Token::Word(word) => pipeline.stages.last_mut().unwrap().push(word),
+            Token::Pipe => {
+                if pipeline.stages.last().unwrap().is_empty() {
+                    return Err(error(ParseErrorKind::MissingCommand, column));
+                }
+                if let Some(redirect_column) = redirect_column {
+                    return Err(error(ParseErrorKind::MisplacedRedirect, redirect_column));
+                }
+                pipeline.stages.push(Vec::new());
+                pipe_column = Some(column);
+            }
+            Token::RedirectStdout { append } | Token::RedirectStderr { append } => {
+                let path = match tokens.next() {
+                    Some((Token::Word(path), _)) => path,
+                    _ => return Err(error(ParseErrorKind::MissingRedirectTarget, column)),
+                };
+                if let Token::RedirectStdout { .. } = token {
+                    pipeline.stdout = Some((path, append));
+                }
+                else {
+                    pipeline.stderr = Some((path, append));
+                }
+                redirect_column = Some(column);
+            }
+            Token::And | Token::Or | Token::Semicolon => {
+                return Err(error(ParseErrorKind::UnexpectedOperator, column));
+            }
+        }
+    }
+    if let (Some(column), true) = (pipe_column, pipeline.stages.last().unwrap().is_empty()) {
+        return Err(error(ParseErrorKind::MissingCommand, column));
+    }
+    Ok(pipeline)
+}
+
+type Chars<'a> = Peekable<Enumerate<str::Chars<'a>>>;
+
+/// Splits `cmd` into tokens, each paired with the 1-based column it starts at.
+pub(crate) fn tokenize(cmd: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let error = |kind, column| ParseError { kind, column, cmd: cmd.to_string() };
+
+    let mut tokens = Vec::new();
+    // The current word and the column it started at.
+    // Tracked separately from the contents of the word so that `""` still produces a word.
+    let mut word: Option<(Word, usize)> = None;
+    let mut chars = cmd.chars().enumerate().peekable();
+
+    fn started(word: &mut Option<(Word, usize)>, column: usize) -> &mut Word {
+        &mut word.get_or_insert_with(|| (Word::default(), column)).0
+    }
+
+    while let Some((i, c)) = chars.next() {
+        let column = i + 1;
+        match c {
+            '\'' => {
+                let word = started(&mut word, column);
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => word.push(c, true),
+                        None => return Err(error(ParseErrorKind::UnterminatedSingleQuote, column)),
+                    }
+                }
+            }
+            '"' => {
+                let word = started(&mut word, column);
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.peek() {
+                            Some(&(_, next @ '"')) | Some(&(_, next @ '\\')) |
+                            Some(&(_, next @ '$')) | Some(&(_, next @ '`')) => {
+                                word.push(next, true);
+                                chars.next();
+                            }
+                            Some(&(_, '\n')) => {
+                                chars.next();
+                            }
+                            _ => word.push('\\', true),
+                        }
+                        Some((i, '$')) => match parse_var(&mut chars, i + 1, cmd)? {
+                            Some(var) => word.parts.push(var),
+                            None => word.push('$', true),
+                        }
+                        Some((_, c)) => word.push(c, true),
+                        None => return Err(error(ParseErrorKind::UnterminatedDoubleQuote, column)),
+                    }
+                }
+            }
+            '\\' => {
+                match chars.next() {
+                    // A backslash-newline is a line continuation
+                    Some((_, '\n')) => {}
+                    Some((_, c)) => started(&mut word, column).push(c, true),
+                    None => return Err(error(ParseErrorKind::TrailingBackslash, column)),
+                }
+            }
+            '$' => {
+                let var = parse_var(&mut chars, column, cmd)?;
+                let word = started(&mut word, column);
+                match var {
+                    Some(var) => word.parts.push(var),
+                    None => word.push('$', false),
+                }
+            }
+            '|' | '&' | ';' => {
+                if let Some((word, start)) = word.take() {
+                    tokens.push((Token::Word(word), start));
+                }
+                if c == '&' && chars.peek().is_some_and(|&(_, next)| next == '>') {
+                    return Err(error(ParseErrorKind::UnsupportedRedirect, column));
+                }
+                let token = match c {
+                    ';' => Token::Semicolon,
+                    _ => match (c, chars.next_if(|&(_, next)| next == c).is_some()) {
+                        ('|', false) => Token::Pipe,
+                        ('|', true) => Token::Or,
+                        ('&', true) => Token::And,
+                        _ => return Err(error(ParseErrorKind::UnsupportedOperator, column)),
+                    }
+                };
+                tokens.push((token, column));
+            }
+            '>' => {
+                let append = chars.next_if(|&(_, c)| c == '>').is_some();
+                if chars.peek().is_some_and(|&(_, c)| c == '&') {
+                    // Reported at the `2` of `2>&1` when there is one
+                    let start = match &word {
+                        Some((word, start)) if word.literal() == "2" && *start == column - 1 => *start,
+                        _ => column,
+                    };
+                    return Err(error(ParseErrorKind::UnsupportedRedirect, start));
+                }
+                // An unquoted `2` directly before the `>` selects stderr rather than being an argument.
+                let stderr = Word { parts: vec![Part::Text { text: "2".to_string(), quoted: false }] };
+                if word == Some((stderr, column - 1)) {
+                    word = None;
+                    tokens.push((Token::RedirectStderr { append }, column - 1));
+                }
+                else {
+                    if let Some((word, start)) = word.take() {
+                        tokens.push((Token::Word(word), start));
+                    }
+                    tokens.push((Token::RedirectStdout { append }, column));
+                }
+            }
+            // A `#` starting a word comments out the rest of the line
+            '#' if word.is_none() => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            c if c.is_whitespace() => {
+                if let Some((word, start)) = word.take() {
+                    tokens.push((Token::Word(word), start));
+                }
+            }
+            c => started(&mut word, column).push(c, false),
+        }
+    }
+    if let Some((word, start)) = word {
+        tokens.push((Token::Word(word), start));
+    }
+
+    Ok(tokens)
+}
+
+/// Parses the variable following a `$` at `column`.
+///
+/// Returns `None` if the `$` is not followed by a variable, in which case it is a literal `$`.
+fn parse_var(chars: &mut Chars, column: usize, cmd: &str) -> Result<Option<Part>, ParseError> {
+    let error = |kind| ParseError { kind, column, cmd: cmd.to_string() };
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    match chars.peek() {
+        Some(&(_, '{')) => {
+            chars.next();
+            let mut contents = String::new();
+            // Variables nested in the default have braces of their own
+            let mut depth = 0;
+            loop {
+                match chars.next() {
+                    Some((_, '}')) if depth == 0 => break,
+                    Some((_, c)) => {
+                        match c {
+                            '{' if contents.ends_with('$') => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                        contents.push(c);
+                    }
+                    None => return Err(error(ParseErrorKind::UnterminatedVariable)),
+                }
+            }
+            let raw = format!("${{{}}}", contents);
+            let (name, default) = match contents.find(":-") {
+                Some(i) => (contents[..i].to_string(), Some(parse_default(&contents[i + 2..], column, cmd)?)),
+                None => (contents, None),
+            };
+            if !name.starts_with(is_name_start) || !name.chars().all(is_name) {
+                return Err(error(ParseErrorKind::InvalidVariable));
+            }
+            Ok(Some(Part::Var { name, default, raw }))
+        }
+        Some(&(_, c)) if is_name_start(c) => {
+            let mut name = String::new();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| is_name(c)) {
+                name.push(c);
+            }
+            let raw = format!("${}", name);
+            Ok(Some(Part::Var { name, default: None, raw }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parses the default of the variable at `column`, which is taken as written apart from the
+/// variables it contains.
+fn parse_default(default: &str, column: usize, cmd: &str) -> Result<Word, ParseError> {
+    let mut word = Word::default();
+    let mut chars = default.chars().enumerate().peekable();
+    while let Some((_, c)) = chars.next() {
+        match c {
+            '$' => match parse_var(&mut chars, column, cmd)? {
+                Some(var) => word.parts.push(var),
+                None => word.push('$', true),
+            }
+            c => word.push(c, true),
+        }
+    }
+    Ok(word)
+}
+
+/// Quotes `word` so that `split` would return it unchanged as a single word.
+pub fn quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        word.to_string()
+    }
+    else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(cmd: &str) -> ParseError {
+        tokenize(cmd).map(|_| ()).unwrap_err()
+    }
+
+    #[test]
+    fn split_quotes_and_escapes() {
+        assert_eq!(split("a  'b c' \"d $e\" f\\ g").unwrap(), ["a", "b c", "d $e", "f g"]);
+        assert_eq!(split(r#""a \"b\" \\ \$ \x""#).unwrap(), [r#"a "b" \ $ \x"#]);
+        assert_eq!(split(r"'a\b' a\'b").unwrap(), [r"a\b", "a'b"]);
+        assert_eq!(split("a'b'\"c\"d").unwrap(), ["abcd"]);
+        assert_eq!(split("a \\\nb").unwrap(), ["a", "b"]);
+    }
+
+    #[test]
+    fn split_empty_words() {
+        assert_eq!(split("'' \"\" a''").unwrap(), ["", "", "a"]);
+        assert!(split("").unwrap().is_empty());
+        assert!(split("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn split_rejects_operators() {
+        for (cmd, column) in [("a | b", 3), ("a && b", 3), ("a; b", 2), ("a > b", 3)] {
+            let err = split(cmd).unwrap_err();
+            assert_eq!((err.kind, err.column), (ParseErrorKind::UnexpectedOperator, column), "{}", cmd);
+        }
+        assert_eq!(split(r"a '|' \;").unwrap(), ["a", "|", ";"]);
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        for word in ["abc", "", "a b", "it's", "$HOME", "a\\b", "\"", "-x=1"] {
+            assert_eq!(split(&quote(word)).unwrap(), [word]);
+        }
+        assert_eq!(quote("a/b-c.d"), "a/b-c.d");
+    }
+
+    #[test]
+    fn operators() {
+        let tokens: Vec<Token> = tokenize("a|b||c&&d;e").unwrap().into_iter().map(|(token, _)| token).collect();
+        let word = |text: &str| Token::Word(Word { parts: vec![Part::Text { text: text.to_string(), quoted: false }] });
+        assert_eq!(tokens, [
+            word("a"), Token::Pipe, word("b"), Token::Or, word("c"), Token::And, word("d"), Token::Semicolon, word("e"),
+        ]);
+
+        let err = error("sleep 1 &");
+        assert_eq!((err.kind, err.column), (ParseErrorKind::UnsupportedOperator, 9));
+    }
+
+    #[test]
+    fn descriptor_redirects() {
+        for (cmd, column) in [("cmd > out 2>&1", 11), ("cmd >&2", 5), ("cmd 2>>&1", 5), ("cmd &> out", 5), ("a2>&1", 3)] {
+            let err = error(cmd);
+            assert_eq!((err.kind, err.column), (ParseErrorKind::UnsupportedRedirect, column), "{}", cmd);
+        }
+        assert!(error("cmd 2>&1").to_string().starts_with("Redirecting to a file descriptor such as 2>&1 is not supported"));
+    }
+
+    #[test]
+    fn stderr_redirects() {
+        let pipeline = parse_pipeline("cmd 2> err >> out").unwrap();
+        assert_eq!(pipeline.stages.len(), 1);
+        assert_eq!(pipeline.stages[0].len(), 1);
+        assert_eq!(pipeline.stderr.map(|(word, append)| (word.literal(), append)), Some(("err".to_string(), false)));
+        assert_eq!(pipeline.stdout.map(|(word, append)| (word.literal(), append)), Some(("out".to_string(), true)));
+
+        // Only an unquoted `2` directly before the `>` selects stderr
+        for cmd in ["echo 2 > out", "echo '2'> out", "echo a2> out", "echo \\2> out"] {
+            let pipeline = parse_pipeline(cmd).unwrap();
+            assert!(pipeline.stderr.is_none(), "{}", cmd);
+            assert_eq!(pipeline.stages[0].len(), 2, "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(split("a # b c").unwrap(), ["a"]);
+        assert_eq!(split("# a").unwrap(), Vec::<String>::new());
+        assert_eq!(split("a#b '#c' \\#d").unwrap(), ["a#b", "#c", "#d"]);
+        let tokens = tokenize("a # b\nc").unwrap();
+        assert_eq!(tokens.iter().map(|(_, column)| *column).collect::<Vec<_>>(), [1, 7]);
+    }
+
+    #[test]
+    fn error_columns() {
+        let cases = [
+            ("echo 'abc", ParseErrorKind::UnterminatedSingleQuote, 6),
+            ("echo a \"bc", ParseErrorKind::UnterminatedDoubleQuote, 8),
+            ("echo a\\", ParseErrorKind::TrailingBackslash, 7),
+            ("echo ${HOME", ParseErrorKind::UnterminatedVariable, 6),
+            ("echo ${1x}", ParseErrorKind::InvalidVariable, 6),
+        ];
+        for (cmd, kind, column) in cases {
+            let err = error(cmd);
+            assert_eq!((err.kind, err.column), (kind, column), "{}", cmd);
+        }
+
+        let pipeline_error = |cmd| parse_script(cmd).map(|_| ()).unwrap_err();
+        let cases = [
+            ("| a", ParseErrorKind::MissingCommand, 1),
+            ("a |", ParseErrorKind::MissingCommand, 3),
+            ("a && ", ParseErrorKind::MissingCommand, 3),
+            ("a > ", ParseErrorKind::MissingRedirectTarget, 3),
+            ("a > f | b", ParseErrorKind::MisplacedRedirect, 3),
+        ];
+        for (cmd, kind, column) in cases {
+            let err = pipeline_error(cmd);
+            assert_eq!((err.kind, err.column), (kind, column), "{}", cmd);
+        }
+    }
+
+    /// Expands every word of `cmd` with only the given variables set.
+    fn expand(cmd: &str, vars: &[(&str, &str)]) -> Result<Vec<String>, String> {
+        let mut shell = Shell::default();
+        for (name, value) in vars {
+            shell.vars.insert(name.to_string(), value.into());
+        }
+        tokenize(cmd).unwrap().into_iter()
+            .map(|(token, _)| match token {
+                Token::Word(word) => word.expand(&shell).map(|value| value.into_string().unwrap()),
+                token => panic!("not a word: {:?}", token),
+            })
+            .collect()
+    }
+
+    fn words(words: &[&str]) -> Result<Vec<String>, String> {
+        Ok(words.iter().map(|word| word.to_string()).collect())
+    }
+
+    #[test]
+    fn variables() {
+        let vars = [("A", "1"), ("B", "x y")];
+        assert_eq!(expand("$A ${A} a$A.b ${A}b", &vars), words(&["1", "1", "a1.b", "1b"]));
+        // A value containing spaces is still a single word
+        assert_eq!(expand("$B \"$B\"", &vars), words(&["x y", "x y"]));
+        // `$` not followed by a name is literal
+        assert_eq!(expand("$ a$ $1 \"$\"", &vars), words(&["$", "a$", "$1", "$"]));
+    }
+
+    #[test]
+    fn single_quotes_suppress_variables() {
+        assert_eq!(expand("'$A' '${A}' \\$A", &[("A", "1")]), words(&["$A", "${A}", "$A"]));
+    }
+
+    #[test]
+    fn defaults() {
+        let vars = [("A", "1"), ("EMPTY", ""), ("HOST_CC", "clang")];
+        assert_eq!(expand("${A:-2} ${EMPTY:-cc} ${SIMPLE_COMMAND_TEST_UNSET:-cc}", &vars), words(&["1", "cc", "cc"]));
+        assert_eq!(expand("${EMPTY}", &vars), words(&[""]));
+        assert_eq!(expand("${EMPTY:-}", &vars), words(&[""]));
+        assert_eq!(expand("'${A:-2}'", &vars), words(&["${A:-2}"]));
+        // Defaults are taken as written apart from their own variables
+        assert_eq!(expand("${SIMPLE_COMMAND_TEST_UNSET:-a b'c}", &vars), words(&["a b'c"]));
+        assert_eq!(expand("${SIMPLE_COMMAND_TEST_UNSET:-$HOST_CC}", &vars), words(&["clang"]));
+        assert_eq!(expand("${SIMPLE_COMMAND_TEST_UNSET:-${EMPTY:-gcc}-12}", &vars), words(&["gcc-12"]));
+    }
+
+    #[test]
+    fn missing_variables() {
+        assert_eq!(expand("a $SIMPLE_COMMAND_TEST_UNSET", &[]), Err("SIMPLE_COMMAND_TEST_UNSET".to_string()));
+        assert_eq!(expand("${SIMPLE_COMMAND_TEST_UNSET}", &[]), Err("SIMPLE_COMMAND_TEST_UNSET".to_string()));
+        assert_eq!(expand("${A:-$SIMPLE_COMMAND_TEST_UNSET}", &[]), Err("SIMPLE_COMMAND_TEST_UNSET".to_string()));
+        // A variable that is set but empty is not missing
+        assert_eq!(expand("$EMPTY", &[("EMPTY", "")]), words(&[""]));
+
+        let err = tokenize("echo ${A:-${B}").map(|_| ()).unwrap_err();
+        assert_eq!((err.kind, err.column), (ParseErrorKind::UnterminatedVariable, 6));
+    }
+
+    #[test]
+    fn error_caret_under_column() {
+        let err = error("echo 'abc");
+        assert_eq!(err.to_string(), "Unterminated single quote at column 6\necho 'abc\n     ^");
+    }
+}
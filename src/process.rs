@@ -0,0 +1,147 @@
+//! Platform specific management of spawned processes.
+
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+/// How long a process group is given to exit after SIGTERM before it is sent SIGKILL.
+#[cfg(unix)]
+const TERM_GRACE: Duration = Duration::from_millis(500);
+
+/// How long to wait for a process group to disappear after SIGKILL.
+#[cfg(unix)]
+const KILL_GRACE: Duration = Duration::from_millis(500);
+
+#[cfg(unix)]
+mod sys {
+    pub const SIGKILL: i32 = 9;
+    pub const SIGTERM: i32 = 15;
+
+    extern "C" {
+        pub fn kill(pid: i32, sig: i32) -> i32;
+    }
+}
+
+/// Places the command in the process group led by `leader`, or a new process group if `None`, so
+/// that it can be killed along with every process it spawns.
+pub(crate) fn set_process_group(command: &mut Command, leader: Option<u32>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(leader.unwrap_or(0) as i32);
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (command, leader);
+    }
+}
+
+/// Owns the children spawned into a process group by `set_process_group`, terminating the whole
+/// group if dropped before `release` is called.
+///
+/// This ensures that no descendant of a failed command is left behind, e.g. compilers forked by
+/// `make` that would otherwise keep writing into `OUT_DIR` after the build script panicked.
+pub(crate) struct ProcessGroup {
+    children: Vec<Child>,
+    done: bool,
+}
+
+impl ProcessGroup {
+    pub fn new() -> ProcessGroup {
+        ProcessGroup { children: Vec::new(), done: false }
+    }
+
+    /// The pid of the first child, which leads the group.
+    pub fn leader(&self) -> Option<u32> {
+        self.children.first().map(|child| child.id())
+    }
+
+    pub fn push(&mut self, child: Child) {
+        self.children.push(child);
+    }
+
+    /// Returns the status of every child once they have all exited.
+    pub fn try_wait(&mut self) -> io::Result<Option<Vec<ExitStatus>>> {
+        let mut statuses = Vec::new();
+        for child in &mut self.children {
+            match child.try_wait()? {
+                Some(status) => statuses.push(status),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(statuses))
+    }
+
+    pub fn wait(&mut self) -> io::Result<Vec<ExitStatus>> {
+        self.children.iter_mut().map(|child| child.wait()).collect()
+    }
+
+    /// Sends SIGTERM to every process in the group, followed by SIGKILL to anything still alive
+    /// after a grace period.
+    /// The children themselves are reaped afterwards.
+    pub fn terminate(&mut self) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+
+        #[cfg(unix)]
+        if let Some(leader) = self.leader() {
+            // The leader's pid is the group id.
+            let group = leader as i32;
+            if self.signal(group, sys::SIGTERM) && !self.wait_for_group(group, TERM_GRACE) {
+                self.signal(group, sys::SIGKILL);
+                // SIGKILL is delivered asynchronously, wait so that nothing in the group is
+                // still running once this returns.
+                self.wait_for_group(group, KILL_GRACE);
+            }
+        }
+
+        // Also covers platforms without process groups.
+        for child in &mut self.children {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+
+    /// Disarms the guard, leaving any remaining processes running.
+    pub fn release(mut self) {
+        self.done = true;
+    }
+
+    /// Waits up to `timeout` for every process in `group` to exit, returning true if they did.
+    #[cfg(unix)]
+    fn wait_for_group(&mut self, group: i32, timeout: Duration) -> bool {
+        let start = Instant::now();
+        loop {
+            // Reap the children, otherwise they keep the group alive as zombies.
+            for child in &mut self.children {
+                child.try_wait().ok();
+            }
+            // Signal 0 only checks whether any process in the group still exists.
+            if !self.signal(group, 0) {
+                return true;
+            }
+            if start.elapsed() >= timeout {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Sends `signal` to every process in `group`, returning false if there were none.
+    #[cfg(unix)]
+    fn signal(&self, group: i32, signal: i32) -> bool {
+        // A negative pid signals every process in the group.
+        unsafe { sys::kill(-group, signal) == 0 }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        self.terminate();
+    }
+}
@@ -0,0 +1,101 @@
+//! Redirecting the output of a command into files without leaving partially written files behind.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A file to redirect an output stream of a command into.
+///
+/// The file is written atomically: output goes to a temporary file next to it which only replaces
+/// the file once the command succeeds.
+/// A failed command leaves the file untouched, so cargo never compiles a half written generated file.
+///
+/// Like a shell, a redirect to a symlink writes to the file it points to, and an existing file
+/// keeps its permissions.
+/// Unlike a shell, the replaced file is a new file, so it does not keep its owner and any other
+/// hard links to it keep the old contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    /// Replaces the contents of the file, like `>`.
+    Truncate(PathBuf),
+    /// Appends to the contents of the file, like `>>`.
+    Append(PathBuf),
+}
+
+impl Redirect {
+    pub fn path(&self) -> &Path {
+        match self {
+            Redirect::Truncate(path) | Redirect::Append(path) => path,
+        }
+    }
+}
+
+/// A temporary file that replaces its target on `commit`, or is removed if dropped without committing.
+#[derive(Debug)]
+pub(crate) struct AtomicFile {
+    temp: PathBuf,
+    target: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates the temporary file for `redirect`, returning it along with a handle to write to it.
+    pub fn create(redirect: &Redirect) -> io::Result<(AtomicFile, File)> {
+        let target = resolve_symlink(redirect.path());
+        let mut name = target.file_name().unwrap_or_default().to_os_string();
+        // Unique within the process as well, as concurrent commands may redirect to the same target
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        name.push(format!(".{}.{}.tmp", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+        let temp = target.with_file_name(name);
+
+        let atomic = AtomicFile { temp, target, committed: false };
+
+        let file = match redirect {
+            Redirect::Truncate(_) => File::create(&atomic.temp)?,
+            Redirect::Append(_) => {
+                match fs::copy(&atomic.target, &atomic.temp) {
+                    Ok(_) => {}
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+                OpenOptions::new().create(true).append(true).open(&atomic.temp)?
+            }
+        };
+        if let Ok(metadata) = fs::metadata(&atomic.target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        Ok((atomic, file))
+    }
+
+    /// Replaces the target with the temporary file.
+    pub fn commit(mut self) -> io::Result<()> {
+        fs::rename(&self.temp, &self.target)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+/// The file a symlink points to, so that replacing it leaves the symlink in place.
+fn resolve_symlink(path: &Path) -> PathBuf {
+    if !fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        return path.to_path_buf();
+    }
+    match fs::canonicalize(path) {
+        Ok(target) => target,
+        // A dangling symlink, whose target the command creates
+        Err(_) => match fs::read_link(path) {
+            Ok(link) => path.parent().unwrap_or(Path::new("")).join(link),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            fs::remove_file(&self.temp).ok();
+        }
+    }
+}
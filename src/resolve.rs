@@ -0,0 +1,227 @@
+//! Resolves the program of a command against `PATH` before it is spawned, so that a missing
+//! program can be reported with enough detail to fix it.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::SimpleCommandError;
+
+/// How many close names are suggested for a missing program.
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Checks that the program of `command` exists and is executable.
+///
+/// `cmd` is used to refer to the command in the returned error.
+pub(crate) fn check(command: &Command, cmd: &str) -> Result<(), SimpleCommandError> {
+    let program = Path::new(command.get_program());
+    let not_found = |searched, not_executable, suggestions| SimpleCommandError::NotFound {
+        cmd: cmd.to_string(),
+        program: program.to_string_lossy().into_owned(),
+        searched,
+        not_executable,
+        suggestions,
+    };
+
+    // A program containing a separator is run as a path rather than searched for.
+    if program.components().count() > 1 {
+        // Relative paths are resolved from the working directory of the command.
+        let path = match command.get_current_dir() {
+            Some(dir) => dir.join(program),
+            None => program.to_path_buf(),
+        };
+        return match candidates(&path).into_iter().find(|path| path.is_file()) {
+            Some(path) if is_executable(&path) => Ok(()),
+            Some(path) => Err(not_found(Vec::new(), vec![path], Vec::new())),
+            None => Err(not_found(Vec::new(), Vec::new(), Vec::new())),
+        };
+    }
+
+    let searched: Vec<PathBuf> = search_path(command).map(|path| env::split_paths(&path).collect()).unwrap_or_default();
+    let mut not_executable = Vec::new();
+    for dir in &searched {
+        for path in candidates(&dir.join(program)) {
+            if path.is_file() {
+                if is_executable(&path) {
+                    return Ok(());
+                }
+                not_executable.push(path);
+            }
+        }
+    }
+
+    let suggestions = suggest(&program.to_string_lossy(), &searched);
+    Err(not_found(searched, not_executable, suggestions))
+}
+
+/// The `PATH` the command is run with, which the command may override.
+fn search_path(command: &Command) -> Option<OsString> {
+    match command.get_envs().find(|(key, _)| *key == "PATH") {
+        Some((_, path)) => path.map(|path| path.to_os_string()),
+        None => env::var_os("PATH"),
+    }
+}
+
+/// The files that `path` may refer to when run.
+fn candidates(path: &Path) -> Vec<PathBuf> {
+    #[cfg(windows)]
+    {
+        let mut candidates = vec![path.to_path_buf()];
+        if path.extension().is_none() {
+            let extensions = env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+            for extension in extensions.split(';').filter(|extension| !extension.is_empty()) {
+                let mut candidate = path.as_os_str().to_os_string();
+                candidate.push(extension);
+                candidates.push(candidate.into());
+            }
+        }
+        candidates
+    }
+    #[cfg(not(windows))]
+    vec![path.to_path_buf()]
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    path.is_file()
+}
+
+/// Names of programs in `dirs` that are close to `program`, e.g. `python3` for `python`.
+fn suggest(program: &str, dirs: &[PathBuf]) -> Vec<String> {
+    let program = program.to_lowercase();
+    // Allow roughly one typo for every three characters
+    let max_distance = (program.chars().count() / 3).max(1);
+
+    let mut names = BTreeSet::new();
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if let Ok(name) = entry.file_name().into_string() {
+                names.insert(name);
+            }
+        }
+    }
+
+    let mut suggestions: Vec<(usize, String)> = names.into_iter()
+        .filter_map(|name| {
+            let stem = name.to_lowercase();
+            #[cfg(windows)]
+            let stem = Path::new(&stem).file_stem().map_or(stem.clone(), |stem| stem.to_string_lossy().into_owned());
+            let distance = distance(&program, &stem);
+            (distance > 0 && distance <= max_distance).then_some((distance, name))
+        })
+        .collect();
+    suggestions.sort();
+    suggestions.into_iter().take(MAX_SUGGESTIONS).map(|(_, name)| name).collect()
+}
+
+/// The Levenshtein distance between two strings.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levenshtein_distance() {
+        assert_eq!(distance("", ""), 0);
+        assert_eq!(distance("abc", ""), 3);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("python", "python"), 0);
+        assert_eq!(distance("python", "python3"), 1);
+        assert_eq!(distance("clang", "clnag"), 2);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("cmake", "make"), 1);
+    }
+
+    #[cfg(unix)]
+    mod unix {
+        use std::os::unix::fs::PermissionsExt;
+
+        use super::*;
+
+        /// A fresh directory containing the given files, executable or not.
+        fn dir(name: &str, files: &[(&str, bool)]) -> PathBuf {
+            let dir = env::temp_dir().join(format!("simple_command_resolve_{}_{}", name, std::process::id()));
+            fs::remove_dir_all(&dir).ok();
+            fs::create_dir_all(&dir).unwrap();
+            for &(file, executable) in files {
+                let path = dir.join(file);
+                fs::write(&path, "#!/bin/sh\n").unwrap();
+                let mode = if executable { 0o755 } else { 0o644 };
+                fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            }
+            dir
+        }
+
+        fn not_found(command: &Command) -> (Vec<PathBuf>, Vec<PathBuf>, Vec<String>) {
+            match check(command, "cmd") {
+                Err(SimpleCommandError::NotFound { searched, not_executable, suggestions, .. }) => {
+                    (searched, not_executable, suggestions)
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+
+        #[test]
+        fn searches_path() {
+            let dir = dir("search", &[("tool", true), ("data", false)]);
+            let mut command = Command::new("tool");
+            command.env("PATH", &dir);
+            assert!(check(&command, "tool").is_ok());
+
+            let mut command = Command::new("data");
+            command.env("PATH", &dir);
+            assert_eq!(not_found(&command), (vec![dir.clone()], vec![dir.join("data")], Vec::new()));
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn paths_are_not_searched() {
+            let dir = dir("paths", &[("tool", true), ("data", false)]);
+            assert!(check(&Command::new(dir.join("tool")), "tool").is_ok());
+            assert_eq!(not_found(&Command::new(dir.join("data"))), (Vec::new(), vec![dir.join("data")], Vec::new()));
+            assert_eq!(not_found(&Command::new(dir.join("missing"))), (Vec::new(), Vec::new(), Vec::new()));
+
+            // Relative paths are resolved from the working directory of the command
+            let mut command = Command::new("./tool");
+            command.current_dir(&dir);
+            assert!(check(&command, "./tool").is_ok());
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[test]
+        fn suggests_close_names() {
+            let dir = dir("suggest", &[("python3", true), ("python3.12", true), ("pip", true), ("perl", true)]);
+            let mut command = Command::new("python");
+            command.env("PATH", &dir);
+            let (searched, not_executable, suggestions) = not_found(&command);
+            assert_eq!((searched, not_executable), (vec![dir.clone()], Vec::new()));
+            assert_eq!(suggestions, ["python3"]);
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Reruns commands that fail transiently.
+
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::SimpleCommandError;
+use crate::output::Output;
+
+type StderrMatcher = dyn Fn(&str) -> bool + Send + Sync;
+
+/// How long to wait between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// The same delay before every retry.
+    Fixed(Duration),
+    /// A delay starting at `initial` that doubles with every retry, up to `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff::Fixed(Duration::from_secs(1))
+    }
+}
+
+/// When and how often to rerun a failed command.
+///
+/// By default any failure of the command itself is retried, i.e. a non-zero return value, being
+/// killed, timing out or being rejected by its success criteria.
+/// Once `on_exit_code`, `on_stderr` or `on_stderr_match` are used only failures matching one of
+/// them are retried.
+/// Failures to start the command, such as it not existing, are never retried.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use simple_command::{Backoff, Retry, SimpleCommand};
+///
+/// SimpleCommand::parse("git submodule update --init")
+///     .unwrap()
+///     .retry(Retry::new(3)
+///         .backoff(Backoff::Exponential { initial: Duration::from_secs(1), max: Duration::from_secs(10) })
+///         .on_stderr("Connection reset")
+///         // e.g. `error: could not lock config file .git/config: File exists`
+///         .on_stderr_match(|stderr| {
+///             stderr.lines().any(|line| line.contains("could not lock") && line.ends_with("File exists"))
+///         }))
+///     .run();
+/// ```
+#[derive(Clone)]
+pub struct Retry {
+    attempts: u32,
+    backoff: Backoff,
+    exit_codes: Vec<i32>,
+    stderr_patterns: Vec<String>,
+    stderr_matchers: Vec<Arc<StderrMatcher>>,
+}
+
+impl Retry {
+    /// Runs the command up to `attempts` times in total, including the first.
+    pub fn new(attempts: u32) -> Retry {
+        Retry {
+            attempts: attempts.max(1),
+            backoff: Backoff::default(),
+            exit_codes: Vec::new(),
+            stderr_patterns: Vec::new(),
+            stderr_matchers: Vec::new(),
+        }
+    }
+
+    pub fn backoff(&mut self, backoff: Backoff) -> &mut Retry {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Retries the command when it exits with `code`.
+    pub fn on_exit_code(&mut self, code: i32) -> &mut Retry {
+        self.exit_codes.push(code);
+        self
+    }
+
+    /// Retries the command when its stderr contains `pattern`.
+    ///
+    /// The pattern is matched as a plain substring.
+    pub fn on_stderr<S: Into<String>>(&mut self, pattern: S) -> &mut Retry {
+        self.stderr_patterns.push(pattern.into());
+        self
+    }
+
+    /// Retries the command when `matcher` returns true for its stderr.
+    ///
+    /// This covers messages a substring cannot describe, the stderr is passed with invalid UTF-8
+    /// replaced so it can be handed straight to a regex.
+    pub fn on_stderr_match<F>(&mut self, matcher: F) -> &mut Retry
+        where F: Fn(&str) -> bool + Send + Sync + 'static
+    {
+        self.stderr_matchers.push(Arc::new(matcher));
+        self
+    }
+
+    fn should_retry(&self, err: &SimpleCommandError) -> bool {
+        let (code, output) = match err {
+            SimpleCommandError::NonZeroExit { code, output, .. } => (Some(*code), output),
+            SimpleCommandError::KilledBySignal { output, .. } => (None, output),
+            SimpleCommandError::Rejected { output, .. } => (output.status().code(), output),
+            SimpleCommandError::TimedOut { output, .. } => (None, output),
+            _ => return false,
+        };
+        if self.exit_codes.is_empty() && self.stderr_patterns.is_empty() && self.stderr_matchers.is_empty() {
+            return true;
+        }
+
+        let stderr = output.stderr_lossy();
+        code.is_some_and(|code| self.exit_codes.contains(&code))
+            || self.stderr_patterns.iter().any(|pattern| stderr.contains(pattern.as_str()))
+            || self.stderr_matchers.iter().any(|matcher| matcher(&stderr))
+    }
+
+    /// The delay before the attempt following `failures` failed attempts.
+    fn delay(&self, failures: u32) -> Duration {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.checked_pow(failures - 1).unwrap_or(u32::MAX);
+                initial.checked_mul(factor).map_or(max, |delay| delay.min(max))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Retry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Retry")
+            .field("attempts", &self.attempts)
+            .field("backoff", &self.backoff)
+            .field("exit_codes", &self.exit_codes)
+            .field("stderr_patterns", &self.stderr_patterns)
+            .field("stderr_matchers", &self.stderr_matchers.len())
+            .finish()
+    }
+}
+
+/// Calls `attempt` until it succeeds, fails in a way `retry` does not cover, or runs out of attempts.
+///
+/// `cmd` is used to refer to the command when reporting the failure of several attempts.
+pub(crate) fn run<F>(retry: &Retry, cmd: &str, mut attempt: F) -> Result<Output, SimpleCommandError>
+    where F: FnMut() -> Result<Output, SimpleCommandError>
+{
+    let mut failures = Vec::new();
+    loop {
+        let err = match attempt() {
+            Ok(output) => return Ok(output),
+            Err(err) => err,
+        };
+        let again = failures.len() + 1 < retry.attempts as usize && retry.should_retry(&err);
+        failures.push(err);
+        if !again {
+            break;
+        }
+        thread::sleep(retry.delay(failures.len() as u32));
+    }
+
+    if failures.len() == 1 {
+        return Err(failures.pop().unwrap());
+    }
+    Err(SimpleCommandError::RetriesFailed { cmd: cmd.to_string(), attempts: failures })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::io;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use super::*;
+    use crate::output::{Chunk, Stage, Stream};
+
+    fn output(status: ExitStatus, stderr: &str) -> Output {
+        Output {
+            stages: vec![Stage { cmd: "cmd".to_string(), status }],
+            chunks: vec![Chunk { stream: Stream::Stderr, elapsed: Duration::ZERO, data: stderr.as_bytes().to_vec() }],
+        }
+    }
+
+    fn exit(code: i32, stderr: &str) -> SimpleCommandError {
+        let output = output(ExitStatus::from_raw(code << 8), stderr);
+        SimpleCommandError::NonZeroExit { cmd: "cmd".to_string(), code, stage: 0, output }
+    }
+
+    fn killed(stderr: &str) -> SimpleCommandError {
+        let status = ExitStatus::from_raw(9);
+        SimpleCommandError::KilledBySignal {
+            cmd: "cmd".to_string(),
+            status,
+            stage: 0,
+            signal: Some(9),
+            core_dumped: false,
+            output: output(status, stderr),
+        }
+    }
+
+    fn seconds(delays: &[u64]) -> Vec<Duration> {
+        delays.iter().map(|&delay| Duration::from_secs(delay)).collect()
+    }
+
+    #[test]
+    fn fixed_delay() {
+        let retry = Retry::new(5);
+        assert_eq!((1..5).map(|failures| retry.delay(failures)).collect::<Vec<_>>(), seconds(&[1, 1, 1, 1]));
+    }
+
+    #[test]
+    fn exponential_delay() {
+        let mut retry = Retry::new(10);
+        retry.backoff(Backoff::Exponential { initial: Duration::from_secs(1), max: Duration::from_secs(10) });
+        let delays: Vec<Duration> = (1..7).map(|failures| retry.delay(failures)).collect();
+        assert_eq!(delays, seconds(&[1, 2, 4, 8, 10, 10]));
+        // Doubling far past the cap neither overflows nor exceeds it
+        assert_eq!(retry.delay(40), Duration::from_secs(10));
+        assert_eq!(retry.delay(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn retries_every_failure_by_default() {
+        let retry = Retry::new(3);
+        assert!(retry.should_retry(&exit(1, "")));
+        assert!(retry.should_retry(&killed("")));
+        let timed_out = SimpleCommandError::TimedOut {
+            cmd: "cmd".to_string(),
+            timeout: Duration::from_secs(1),
+            output: output(ExitStatus::from_raw(9), ""),
+        };
+        assert!(retry.should_retry(&timed_out));
+        let rejected = SimpleCommandError::Rejected {
+            cmd: "cmd".to_string(),
+            reason: "wrote \"error\" to stderr".to_string(),
+            output: output(ExitStatus::from_raw(0), "error"),
+        };
+        assert!(retry.should_retry(&rejected));
+
+        // The command never ran, so running it again will not help
+        let spawn_failed = SimpleCommandError::SpawnFailed { cmd: "cmd".to_string(), source: io::ErrorKind::PermissionDenied.into() };
+        assert!(!retry.should_retry(&spawn_failed));
+        assert!(!retry.should_retry(&SimpleCommandError::NoCommand));
+    }
+
+    #[test]
+    fn filters() {
+        let mut retry = Retry::new(3);
+        retry.on_exit_code(128);
+        assert!(retry.should_retry(&exit(128, "")));
+        assert!(!retry.should_retry(&exit(1, "")));
+        assert!(!retry.should_retry(&killed("")));
+
+        retry.on_stderr("Connection reset");
+        assert!(retry.should_retry(&exit(1, "fatal: Connection reset by peer")));
+        assert!(retry.should_retry(&killed("Connection reset")));
+        assert!(!retry.should_retry(&exit(1, "fatal: connection reset")));
+
+        let mut retry = Retry::new(3);
+        retry.on_stderr_match(|stderr| stderr.lines().any(|line| line.starts_with("error: lock")));
+        assert!(retry.should_retry(&exit(1, "warning: x\nerror: lock held\n")));
+        assert!(!retry.should_retry(&exit(1, "warning: error: lock held\n")));
+    }
+}
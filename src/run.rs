@@ -0,0 +1,355 @@
+//! Spawns a command and captures its output.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::cargo::{CargoWarnings, WarningForwarder};
+use crate::error::SimpleCommandError;
+use crate::jobserver;
+use crate::output::{Chunk, Output, Stage, Stream};
+use crate::parse::quote;
+use crate::process::{self, ProcessGroup};
+use crate::redirect::{AtomicFile, Redirect};
+use crate::resolve;
+use crate::signal;
+use crate::success::{Failure, Success};
+
+/// How long to keep reading output after terminating a command.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// How often to check on the command while waiting for output.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Configuration shared by every way of running a command.
+#[derive(Debug, Default)]
+pub(crate) struct Options {
+    pub cargo_warnings: CargoWarnings,
+    pub inputs: Vec<PathBuf>,
+    pub env_inputs: Vec<OsString>,
+    pub timeout: Option<Duration>,
+    /// `None` leaves stdin as configured on the command.
+    pub stdin: Option<Stdin>,
+    /// Redirects the stdout of the last command.
+    pub stdout: Option<Redirect>,
+    /// Redirects the stderr of every command.
+    pub stderr: Option<Redirect>,
+    pub success: Success,
+}
+
+/// Where a command reads its stdin from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Stdin {
+    /// Stdin is empty, so a command that reads it sees EOF straight away.
+    #[default]
+    Null,
+    /// Stdin is inherited from the build script.
+    Inherit,
+    /// The bytes are written to stdin from a separate thread.
+    Bytes(Vec<u8>),
+    /// Stdin is read from the file.
+    File(PathBuf),
+}
+
+/// Runs a pipeline of commands to completion, capturing the stdout of the last command and the
+/// stderr of every command.
+/// Each command's stdout is connected to the stdin of the next.
+///
+/// `display` is used to refer to the pipeline in any returned error.
+pub(crate) fn run(commands: &mut [Command], display: &str, options: &Options) -> Result<Output, SimpleCommandError> {
+    let stage_displays: Vec<String> = if commands.len() == 1 {
+        vec![display.to_string()]
+    }
+    else {
+        commands.iter().map(describe).collect()
+    };
+    let io_error = |err| SimpleCommandError::IoError { cmd: display.to_string(), source: err };
+
+    let first = commands.first_mut().expect("Pipeline must contain a command");
+    match &options.stdin {
+        None => {}
+        Some(Stdin::Null) => {
+            first.stdin(Stdio::null());
+        }
+        Some(Stdin::Inherit) => {
+            first.stdin(Stdio::inherit());
+        }
+        Some(Stdin::Bytes(_)) => {
+            first.stdin(Stdio::piped());
+        }
+        Some(Stdin::File(path)) => match File::open(path) {
+            Ok(file) => {
+                first.stdin(file);
+            }
+            Err(err) => {
+                return Err(SimpleCommandError::File { cmd: display.to_string(), path: path.clone(), source: err });
+            }
+        }
+    }
+
+    let file_error = |redirect: &Redirect, err| SimpleCommandError::File {
+        cmd: display.to_string(),
+        path: redirect.path().to_path_buf(),
+        source: err,
+    };
+    let mut stdout_file = None;
+    if let Some(redirect) = &options.stdout {
+        stdout_file = Some(AtomicFile::create(redirect).map_err(|err| file_error(redirect, err))?);
+    }
+    let mut stderr_file = None;
+    // Like `> f 2>&1`, stdout and stderr redirected to the same path share one file rather than
+    // two temporary files replacing the target one after the other.
+    let shared = options.stderr.as_ref()
+        .is_some_and(|stderr| options.stdout.as_ref().is_some_and(|stdout| stdout.path() == stderr.path()));
+    if let Some(redirect) = options.stderr.as_ref().filter(|_| !shared) {
+        stderr_file = Some(AtomicFile::create(redirect).map_err(|err| file_error(redirect, err))?);
+    }
+    let stderr_handle = if shared { &stdout_file } else { &stderr_file };
+
+    // Every pipe is drained on its own thread until EOF.
+    // Reading them one after the other would deadlock as soon as a child fills
+    // the pipe we are not currently reading from.
+    let (sender, receiver) = mpsc::channel();
+    let mut readers = Vec::new();
+    let mut stdin = None;
+
+    let start = Instant::now();
+    let mut group = ProcessGroup::new();
+    let mut previous_stdout = None;
+    let last = commands.len() - 1;
+    for (i, command) in commands.iter_mut().enumerate() {
+        if let Some(stdout) = previous_stdout.take() {
+            command.stdin(Stdio::from(stdout));
+        }
+        match &stdout_file {
+            Some((_, file)) if i == last => {
+                command.stdout(file.try_clone().map_err(io_error)?);
+            }
+            _ => {
+                command.stdout(Stdio::piped());
+            }
+        }
+        match stderr_handle {
+            Some((_, file)) => {
+                command.stderr(file.try_clone().map_err(io_error)?);
+            }
+            None => {
+                command.stderr(Stdio::piped());
+            }
+        }
+        process::set_process_group(command, group.leader());
+        jobserver::configure(command);
+
+        // Dropping the group terminates any commands of the pipeline that were already started.
+        resolve::check(command, &stage_displays[i])?;
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            // The program exists, so this is e.g. the interpreter of a script not existing
+            Err(err) => return Err(SimpleCommandError::SpawnFailed { cmd: stage_displays[i].clone(), source: err }),
+        };
+
+        if i > 0 {
+            // The command holds on to the read end of the previous pipe until reconfigured.
+            // Keeping it open would stop the previous command from seeing EPIPE once this one exits.
+            command.stdin(Stdio::null());
+        }
+
+        if let Some(stdout) = child.stdout.take() {
+            if i == last {
+                readers.push(drain(stdout, Stream::Stdout, start, sender.clone()));
+            }
+            else {
+                previous_stdout = Some(stdout);
+            }
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(drain(stderr, Stream::Stderr, start, sender.clone()));
+        }
+        if let (0, Some(Stdin::Bytes(bytes))) = (i, &options.stdin) {
+            stdin = Some(feed(child.stdin.take().expect("Wasn't stdin"), bytes.clone()));
+        }
+
+        group.push(child);
+    }
+    drop(sender);
+
+    let deadline = options.timeout.map(|timeout| start + timeout);
+    let mut timed_out = false;
+    let mut terminated_at = None;
+    let mut readers_finished = false;
+    let mut warnings = WarningForwarder::new(&options.cargo_warnings);
+    let mut chunks = Vec::new();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(chunk) => {
+                warnings.push(&chunk);
+                chunks.push(chunk);
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                readers_finished = true;
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        if let Some(terminated_at) = terminated_at {
+            // Output can only continue past this if a descendant escaped the process group while
+            // holding the pipes, so give up on reading the rest.
+            if Instant::now().duration_since(terminated_at) >= KILL_GRACE {
+                break;
+            }
+        }
+        else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            timed_out = true;
+            group.terminate();
+            terminated_at = Some(Instant::now());
+        }
+        else if group.try_wait().map_err(io_error)?.is_some_and(|statuses| options.success.failing_stage(&statuses).is_some()) {
+            // Descendants of a failed command may keep the pipes open indefinitely,
+            // stop them so the failure is reported straight away.
+            group.terminate();
+            terminated_at = Some(Instant::now());
+        }
+    }
+    warnings.finish();
+    // Readers that never finished are left behind rather than blocking forever.
+    if readers_finished {
+        for reader in readers {
+            reader.join().expect("output reader panicked").map_err(io_error)?;
+        }
+        if let Some(stdin) = stdin {
+            stdin.join().expect("stdin writer panicked").map_err(io_error)?;
+        }
+    }
+
+    // The children may have closed their output without exiting, so the deadline still applies.
+    let statuses = match deadline {
+        Some(deadline) => loop {
+            if let Some(statuses) = group.try_wait().map_err(io_error)? {
+                break statuses;
+            }
+            if Instant::now() >= deadline {
+                timed_out = true;
+                group.terminate();
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        None => group.wait().map_err(io_error)?,
+    };
+    let stages = stage_displays.into_iter()
+        .zip(statuses)
+        .map(|(cmd, status)| Stage { cmd, status })
+        .collect();
+    let output = Output {
+        stages,
+        chunks,
+    };
+
+    if timed_out {
+        let timeout = options.timeout.unwrap();
+        return Err(SimpleCommandError::TimedOut { cmd: display.to_string(), timeout, output });
+    }
+
+    // Dropping the group on failure terminates any remaining descendants.
+    match options.success.check(&output) {
+        Ok(()) => {}
+        Err(Failure::Status(stage, status)) => match status.code() {
+            Some(code) => return Err(SimpleCommandError::NonZeroExit { cmd: display.to_string(), code, stage, output }),
+            None => return Err(SimpleCommandError::KilledBySignal {
+                cmd: display.to_string(),
+                status,
+                stage,
+                signal: signal::signal(status),
+                core_dumped: signal::core_dumped(status),
+                output,
+            }),
+        }
+        Err(Failure::Rejected(reason)) => {
+            return Err(SimpleCommandError::Rejected { cmd: display.to_string(), reason, output });
+        }
+    }
+
+    // Only now that the command succeeded do the redirect targets get replaced.
+    for (redirect, (file, _)) in options.stdout.iter().zip(stdout_file).chain(options.stderr.iter().zip(stderr_file)) {
+        file.commit().map_err(|err| file_error(redirect, err))?;
+    }
+
+    group.release();
+    Ok(output)
+}
+
+/// Writes `bytes` to `stdin` on a new thread, closing it once done.
+///
+/// Writing happens in parallel with reading the output, a command that echoes its input
+/// would otherwise deadlock once the stdout pipe fills.
+fn feed(mut stdin: ChildStdin, bytes: Vec<u8>) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        match stdin.write_all(&bytes) {
+            // The command is free to exit without reading all of its input.
+            Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    })
+}
+
+/// Reads `reader` until EOF on a new thread, sending each chunk read to `sender`.
+fn drain<R: Read + Send + 'static>(mut reader: R, stream: Stream, start: Instant, sender: Sender<Chunk>) -> JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buffer = [0; 8192];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(len) => {
+                    let chunk = Chunk {
+                        stream,
+                        elapsed: start.elapsed(),
+                        data: buffer[..len].to_vec(),
+                    };
+                    // The receiver only hangs up once we are done, so this cannot fail.
+                    sender.send(chunk).ok();
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    })
+}
+
+/// Renders `command` as a shell command line including its working directory and environment.
+pub(crate) fn describe(command: &Command) -> String {
+    let mut words = Vec::new();
+    if let Some(dir) = command.get_current_dir() {
+        words.push("cd".to_string());
+        words.push(quote(&dir.to_string_lossy()));
+        words.push("&&".to_string());
+    }
+
+    let mut removed = Vec::new();
+    let mut set = Vec::new();
+    for (key, val) in command.get_envs() {
+        let key = key.to_string_lossy();
+        match val {
+            Some(val) => set.push(format!("{}={}", key, quote(&val.to_string_lossy()))),
+            None => removed.push(quote(&key)),
+        }
+    }
+    if !removed.is_empty() {
+        words.push("env".to_string());
+        for key in removed {
+            words.push("-u".to_string());
+            words.push(key);
+        }
+    }
+    words.extend(set);
+
+    words.push(quote(&command.get_program().to_string_lossy()));
+    for arg in command.get_args() {
+        words.push(quote(&arg.to_string_lossy()));
+    }
+    words.join(" ")
+}
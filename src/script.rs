@@ -0,0 +1,401 @@
+//! Runs scripts of pipelines separated by `&&`, `||` and `;`, and script files made up of lines of them.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+use crate::builder::SimpleCommand;
+use crate::error::SimpleCommandError;
+use crate::glob::Glob;
+use crate::output::{Chunk, Output, Stage};
+use crate::parse::{parse_script, tokenize, Connector, ParseError, Pipeline, Step};
+
+/// A step of a script that was run, along with how it went.
+#[derive(Debug)]
+pub struct StepOutcome {
+    pub cmd: String,
+    pub result: Result<Output, SimpleCommandError>,
+}
+
+/// The working directory and variables set by the `cd` and `export` builtins, which persist
+/// across the steps and lines of a script.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Shell {
+    /// The working directory, `None` until the script uses `cd`.
+    pub cwd: Option<PathBuf>,
+    pub vars: BTreeMap<String, OsString>,
+}
+
+impl Shell {
+    /// The value of a variable, preferring those exported by the script over the environment.
+    pub fn var(&self, name: &str) -> Option<OsString> {
+        self.vars.get(name).cloned().or_else(|| env::var_os(name))
+    }
+
+    /// Resolves a path relative to the working directory of the script.
+    pub fn path<P: Into<PathBuf>>(&self, path: P) -> PathBuf {
+        let path = path.into();
+        match &self.cwd {
+            Some(cwd) => cwd.join(path),
+            None => path,
+        }
+    }
+}
+
+/// Runs `cmd`, which may consist of several pipelines separated by `&&`, `||` and `;`.
+pub(crate) fn run_script(cmd: &str, glob: Glob) -> Result<Output, SimpleCommandError> {
+    run_line(cmd, glob, &mut Shell::default())
+}
+
+/// Runs every line of a script file, `name` is used to refer to the script in errors.
+///
+/// Lines ending in a backslash continue onto the next line, while blank lines and lines that
+/// only contain a comment are skipped.
+/// The script stops at the first line that fails.
+pub(crate) fn run_script_file(script: &str, name: &str) -> Result<Output, SimpleCommandError> {
+    let start = Instant::now();
+    let mut shell = Shell::default();
+    let mut chunks = Vec::new();
+    let mut stages = Vec::new();
+    for (line, text) in lines(script) {
+        let error = |err| {
+            let (line, source) = match err {
+                SimpleCommandError::Parse(err) => {
+                    let (offset, err) = physical_line(err);
+                    (line + offset, SimpleCommandError::Parse(err))
+                }
+                err => (line, err),
+            };
+            SimpleCommandError::ScriptLine { script: name.to_string(), line, source: Box::new(source) }
+        };
+        if tokenize(&text).map_err(|err| error(err.into()))?.is_empty() {
+            continue;
+        }
+
+        let offset = start.elapsed();
+        let output = run_line(&text, Glob::Disabled, &mut shell).map_err(error)?;
+        append(&mut chunks, &output, offset);
+        stages = output.stages;
+    }
+    Ok(Output { stages, chunks })
+}
+
+/// Splits a script into logical lines along with the number of the line each starts on.
+fn lines(script: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (i, line) in script.lines().enumerate() {
+        let (_, text) = current.get_or_insert_with(|| (i + 1, String::new()));
+        text.push_str(line);
+        // A backslash escaping the newline is a line continuation for the parser to join
+        if continues(text) {
+            text.push('\n');
+        }
+        else {
+            lines.extend(current.take());
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+/// Narrows an error in a logical line joined from several lines down to the line it is on.
+///
+/// Returns how many lines into the logical line the error is, along with the error relative to
+/// that line alone.
+fn physical_line(err: ParseError) -> (usize, ParseError) {
+    let mut offset = 0;
+    let mut start = 0;
+    for (i, c) in err.cmd.chars().enumerate().take(err.column.saturating_sub(1)) {
+        if c == '\n' {
+            offset += 1;
+            start = i + 1;
+        }
+    }
+    let cmd = err.cmd.chars().skip(start).take_while(|&c| c != '\n').collect();
+    (offset, ParseError { kind: err.kind, column: err.column - start, cmd })
+}
+
+/// Whether `text` ends in a backslash that escapes the newline after it.
+///
+/// Scanned the way the parser reads it, so a backslash inside single quotes or at the end of a
+/// `#` comment does not continue the line.
+fn continues(text: &str) -> bool {
+    let mut chars = text.chars();
+    let mut quote = None;
+    // Whether the next character starts a new word, which is where a `#` starts a comment
+    let mut word_start = true;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => {}
+            // The escaped character is skipped, with nothing left to escape it is the newline
+            (_, '\\') => match chars.next() {
+                Some(_) => {}
+                None => return true,
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '#') if word_start => {
+                // The comment runs to the end of the physical line
+                chars.by_ref().find(|&c| c == '\n');
+                word_start = true;
+                continue;
+            }
+            _ => {}
+        }
+        word_start = quote.is_none() && (c.is_whitespace() || "|&;<>".contains(c));
+    }
+    false
+}
+
+/// Runs a single line of a script.
+///
+/// `&&` and `||` behave as they do in a shell.
+/// The line stops as soon as a step fails unless the failure is handled by a following `||`.
+/// Unlike `set -e` this includes a failure on the left of `&&`, so `false && a; b` never runs `b`.
+fn run_line(cmd: &str, glob: Glob, shell: &mut Shell) -> Result<Output, SimpleCommandError> {
+    let steps = parse_script(cmd)?;
+    if let [step] = steps.as_slice() {
+        return run_step(step, cmd.trim(), glob, shell);
+    }
+
+    let start = Instant::now();
+    let mut outcomes = Vec::new();
+    let mut chunks = Vec::new();
+    let mut stages = Vec::new();
+    let mut failed = None;
+    for step in &steps {
+        match step.connector {
+            // The previous failure was not handled by `||`
+            Connector::Always if failed.is_some() => break,
+            Connector::And if failed.is_some() => continue,
+            Connector::Or if failed.is_none() => continue,
+            _ => {}
+        }
+
+        let offset = start.elapsed();
+        let result = run_step(step, &step.cmd, glob, shell);
+        match &result {
+            Ok(output) => {
+                failed = None;
+                append(&mut chunks, output, offset);
+                stages = output.stages.clone();
+            }
+            Err(_) => failed = Some(outcomes.len()),
+        }
+        outcomes.push(StepOutcome { cmd: step.cmd.clone(), result });
+    }
+
+    match failed {
+        Some(step) => Err(SimpleCommandError::ScriptFailed { cmd: cmd.to_string(), step, steps: outcomes }),
+        None => Ok(Output { stages, chunks }),
+    }
+}
+
+/// Appends the chunks of `output`, timing them from the start of the script rather than the step.
+fn append(chunks: &mut Vec<Chunk>, output: &Output, offset: Duration) {
+    chunks.extend(output.chunks.iter().cloned().map(|mut chunk| {
+        chunk.elapsed += offset;
+        chunk
+    }));
+}
+
+fn run_step(step: &Step, cmd: &str, glob: Glob, shell: &mut Shell) -> Result<Output, SimpleCommandError> {
+    match run_builtin(&step.pipeline, cmd, shell) {
+        Some(result) => result,
+        None => SimpleCommand::from_pipeline(&step.pipeline, cmd, glob, shell)?.try_run(),
+    }
+}
+
+/// Runs the step if it is a `cd` or `export` builtin, returning `None` otherwise.
+///
+/// Builtins change the state of the script rather than running a program, so they are only
+/// recognised on their own rather than as part of a pipeline or with a redirect.
+fn run_builtin(pipeline: &Pipeline, cmd: &str, shell: &mut Shell) -> Option<Result<Output, SimpleCommandError>> {
+    let words = match pipeline.stages.as_slice() {
+        [words] if pipeline.stdout.is_none() && pipeline.stderr.is_none() => words,
+        _ => return None,
+    };
+    let builtin = words.first()?.literal();
+    if builtin != "cd" && builtin != "export" {
+        return None;
+    }
+
+    let error = |message: String| SimpleCommandError::Builtin { cmd: cmd.to_string(), message };
+    let mut args = Vec::new();
+    for word in &words[1..] {
+        match word.expand(shell) {
+            Ok(arg) => args.push(arg),
+            Err(name) => return Some(Err(SimpleCommandError::MissingEnvVar { cmd: cmd.to_string(), name })),
+        }
+    }
+
+    let result = match builtin.as_str() {
+        "cd" => match args.as_slice() {
+            [dir] => {
+                let dir = shell.path(dir);
+                match fs::metadata(&dir) {
+                    Ok(metadata) if metadata.is_dir() => {
+                        shell.cwd = Some(dir);
+                        Ok(())
+                    }
+                    Ok(_) => Err(error(format!("{} is not a directory", dir.display()))),
+                    Err(err) => Err(error(format!("{}: {}", dir.display(), err))),
+                }
+            }
+            _ => Err(error("cd takes exactly one directory".to_string())),
+        }
+        _ => args.iter().try_for_each(|arg| export(shell, arg).map_err(error)),
+    };
+
+    Some(result.map(|()| Output {
+        stages: vec![Stage { cmd: cmd.to_string(), status: ExitStatus::default() }],
+        chunks: Vec::new(),
+    }))
+}
+
+/// Sets the variable from a `NAME=value` argument to `export`.
+///
+/// A bare `NAME` is accepted as it is already exported if it is set at all.
+fn export(shell: &mut Shell, arg: &OsString) -> Result<(), String> {
+    let arg = arg.to_str().ok_or_else(|| format!("{} is not valid unicode", arg.to_string_lossy()))?;
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (arg, None),
+    };
+
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("{} is not a valid variable name", name));
+    }
+    if let Some(value) = value {
+        shell.vars.insert(name.to_string(), value.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stdout(cmd: &str) -> String {
+        match run_script(cmd, Glob::Disabled) {
+            Ok(output) => output.stdout_lossy(),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// The commands of the steps that ran before the script stopped, and the index of the failed one.
+    fn failure(cmd: &str) -> (Vec<String>, usize) {
+        match run_script(cmd, Glob::Disabled) {
+            Err(SimpleCommandError::ScriptFailed { step, steps, .. }) => (steps.into_iter().map(|step| step.cmd).collect(), step),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn connectors() {
+        assert_eq!(stdout("echo a && echo b"), "a\nb\n");
+        assert_eq!(stdout("false && echo a || echo b"), "b\n");
+        assert_eq!(stdout("false || echo a && echo b"), "a\nb\n");
+        assert_eq!(stdout("true || echo a && echo b"), "b\n");
+        assert_eq!(stdout("true || echo a || echo b"), "");
+        assert_eq!(stdout("false || false || echo a"), "a\n");
+        assert_eq!(stdout("echo a; echo b"), "a\nb\n");
+        assert_eq!(stdout("echo a;"), "a\n");
+        assert_eq!(stdout("echo a ; echo b ;"), "a\nb\n");
+    }
+
+    #[test]
+    fn stops_at_unhandled_failure() {
+        assert_eq!(failure("echo a; false; echo b"), (vec!["echo a".to_string(), "false".to_string()], 1));
+        assert_eq!(failure("false && echo a; echo b"), (vec!["false".to_string()], 0));
+        assert_eq!(failure("true && false"), (vec!["true".to_string(), "false".to_string()], 1));
+        assert_eq!(failure("false || false"), (vec!["false".to_string(), "false".to_string()], 1));
+        // A failure handled by `||` does not stop the script
+        assert_eq!(stdout("false || true; echo b"), "b\n");
+    }
+
+    #[test]
+    fn builtins_persist() {
+        assert_eq!(stdout("cd / && pwd"), "/\n");
+        assert_eq!(stdout("cd /; cd tmp; pwd"), "/tmp\n");
+        assert_eq!(stdout("export A=1 B='x y'; echo $A \"$B\"; sh -c 'echo $B'"), "1 x y\nx y\n");
+        assert_eq!(stdout("export A=1 && export A=${A}2 && echo $A"), "12\n");
+
+        let output = crate::simple_script_str("cd /\nexport GREETING=hi\nsh -c 'pwd; echo $GREETING'\n");
+        assert_eq!(output.stdout_lossy(), "/\nhi\n");
+    }
+
+    #[test]
+    fn builtin_failures() {
+        match run_script("cd /does/not/exist", Glob::Disabled) {
+            Err(SimpleCommandError::Builtin { cmd, .. }) => assert_eq!(cmd, "cd /does/not/exist"),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(stdout("cd /does/not/exist || echo handled"), "handled\n");
+    }
+
+    #[test]
+    fn descriptor_redirect_is_rejected() {
+        match run_script("cmd > out 2>&1", Glob::Disabled) {
+            Err(SimpleCommandError::Parse(err)) => assert_eq!(err.kind, crate::ParseErrorKind::UnsupportedRedirect),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_physical_line() {
+        let script = "true\necho a \\\n  'b\necho c\n";
+        match crate::try_simple_script_str(script).unwrap_err() {
+            SimpleCommandError::ScriptLine { line, source, .. } => {
+                assert_eq!(line, 3);
+                match *source {
+                    SimpleCommandError::Parse(err) => {
+                        assert_eq!((err.column, err.cmd.as_str()), (3, "  'b"));
+                        assert_eq!(err.to_string(), "Unterminated single quote at column 3\n  'b\n  ^");
+                    }
+                    err => panic!("unexpected error: {}", err),
+                }
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+
+        // Errors found once the line is split into steps are narrowed down the same way
+        match crate::try_simple_script_str("echo a && \\\n  && echo b\n").unwrap_err() {
+            SimpleCommandError::ScriptLine { line, source, .. } => {
+                assert_eq!(line, 2);
+                assert!(source.to_string().starts_with("Missing command next to operator at column 3\n  && echo b\n"), "{}", source);
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn continuation_lines_are_joined() {
+        let script = "echo a \\\n  b\necho 'c\\'\n";
+        assert_eq!(lines(script), vec![(1, "echo a \\\n  b".to_string()), (3, "echo 'c\\'".to_string())]);
+    }
+
+    #[test]
+    fn backslash_ending_comment_does_not_continue() {
+        let script = "true\n# comment \\\nfalse\necho a # b \\\nfalse\n";
+        let numbers: Vec<usize> = lines(script).into_iter().map(|(line, _)| line).collect();
+        assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+
+        let err = crate::try_simple_script_str("true\n# comment \\\nfalse\n").unwrap_err();
+        match err {
+            SimpleCommandError::ScriptLine { line, source, .. } => {
+                assert_eq!(line, 3);
+                assert!(source.to_string().contains("\"false\""), "{}", source);
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Describes how a command exited, including the signal that killed it on Unix.
+
+use std::process::ExitStatus;
+
+/// The signal that terminated the process, always `None` on platforms without signals.
+pub(crate) fn signal(status: ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        None
+    }
+}
+
+/// Whether the process dumped core when it was killed.
+pub(crate) fn core_dumped(status: ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.core_dumped()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        false
+    }
+}
+
+/// The name of a Unix signal such as `SIGSEGV`, or `None` if the number is not known.
+///
+/// A command killed by `SIGKILL` that did not time out was most likely killed by the out of memory
+/// killer, whereas `SIGSEGV`, `SIGBUS` or `SIGABRT` point to a crash.
+pub fn signal_name(signal: i32) -> Option<&'static str> {
+    let name = match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        5 => "SIGTRAP",
+        6 => "SIGABRT",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        24 => "SIGXCPU",
+        25 => "SIGXFSZ",
+        _ => return platform_signal_name(signal),
+    };
+    Some(name)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn platform_signal_name(signal: i32) -> Option<&'static str> {
+    match signal {
+        7 => Some("SIGBUS"),
+        10 => Some("SIGUSR1"),
+        12 => Some("SIGUSR2"),
+        31 => Some("SIGSYS"),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn platform_signal_name(signal: i32) -> Option<&'static str> {
+    // The BSDs, including macOS, number these differently
+    match signal {
+        10 => Some("SIGBUS"),
+        12 => Some("SIGSYS"),
+        30 => Some("SIGUSR1"),
+        31 => Some("SIGUSR2"),
+        _ => None,
+    }
+}
+
+/// Describes how a command failed, e.g. `failed with return value 1` or
+/// `was killed by signal 11 (SIGSEGV, core dumped)`.
+pub(crate) fn describe_failure(status: ExitStatus) -> String {
+    if let Some(code) = status.code() {
+        return format!("failed with return value {}", code);
+    }
+    match signal(status) {
+        Some(signal) => {
+            let mut details: Vec<&str> = signal_name(signal).into_iter().collect();
+            if core_dumped(status) {
+                details.push("core dumped");
+            }
+            if details.is_empty() {
+                format!("was killed by signal {}", signal)
+            }
+            else {
+                format!("was killed by signal {} ({})", signal, details.join(", "))
+            }
+        }
+        None => "failed with no return value".to_string(),
+    }
+}
@@ -0,0 +1,168 @@
+//! Decides whether a command that ran to completion succeeded.
+
+use std::fmt;
+use std::process::ExitStatus;
+use std::sync::Arc;
+
+use crate::output::Output;
+use crate::signal;
+
+type Predicate = dyn Fn(ExitStatus, &[u8], &[u8]) -> bool + Send + Sync;
+
+/// What counts as success, by default a return value of 0 from every command of the pipeline.
+#[derive(Clone, Default)]
+pub(crate) struct Success {
+    /// Return values accepted in addition to 0.
+    pub exit_codes: Vec<i32>,
+    /// Replaces the check of the return values when set.
+    pub predicate: Option<Arc<Predicate>>,
+    /// Text that makes the command fail when written to stderr, even if it exited successfully.
+    pub stderr_failures: Vec<String>,
+}
+
+/// Why a command that ran to completion is considered to have failed.
+pub(crate) enum Failure {
+    /// The command of the pipeline at the index exited with a status that is not accepted.
+    Status(usize, ExitStatus),
+    /// The predicate or stderr check rejected the command, for the given reason.
+    Rejected(String),
+}
+
+impl Success {
+    /// Whether a single command exiting with `status` succeeded as far as return values go.
+    fn accepts(&self, status: ExitStatus) -> bool {
+        status.success() || status.code().is_some_and(|code| self.exit_codes.contains(&code))
+    }
+
+    /// The index of the last command of the pipeline whose status is not accepted.
+    ///
+    /// Always `None` when a predicate decides, as that needs the complete output, so this tells
+    /// whether the pipeline is known to have failed before its output has been read.
+    pub fn failing_stage(&self, statuses: &[ExitStatus]) -> Option<usize> {
+        if self.predicate.is_some() {
+            return None;
+        }
+        // Like the default pipefail status, the last command to fail is reported
+        statuses.iter().rposition(|&status| !self.accepts(status))
+    }
+
+    pub fn check(&self, output: &Output) -> Result<(), Failure> {
+        let status = output.status();
+        match &self.predicate {
+            Some(predicate) => {
+                if !predicate(status, &output.stdout(), &output.stderr()) {
+                    let reason = match status.code() {
+                        Some(code) => format!("with return value {} was rejected by its success check", code),
+                        None => format!("{} and was rejected by its success check", signal::describe_failure(status)),
+                    };
+                    return Err(Failure::Rejected(reason));
+                }
+            }
+            None => {
+                let statuses: Vec<ExitStatus> = output.stages().iter().map(|stage| stage.status).collect();
+                if let Some(stage) = self.failing_stage(&statuses) {
+                    return Err(Failure::Status(stage, statuses[stage]));
+                }
+            }
+        }
+
+        if !self.stderr_failures.is_empty() {
+            let stderr = output.stderr_lossy();
+            if let Some(pattern) = self.stderr_failures.iter().find(|pattern| stderr.contains(pattern.as_str())) {
+                return Err(Failure::Rejected(format!("wrote \"{}\" to stderr", pattern)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Success {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Success")
+            .field("exit_codes", &self.exit_codes)
+            .field("predicate", &self.predicate.as_ref().map(|_| "Fn"))
+            .field("stderr_failures", &self.stderr_failures)
+            .finish()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::builder::SimpleCommand;
+    use crate::output::{Chunk, Stage, Stream};
+
+    fn status(code: i32) -> ExitStatus {
+        ExitStatus::from_raw(code << 8)
+    }
+
+    fn output(codes: &[i32], stderr: &str) -> Output {
+        Output {
+            stages: codes.iter().map(|&code| Stage { cmd: "cmd".to_string(), status: status(code) }).collect(),
+            chunks: vec![Chunk { stream: Stream::Stderr, elapsed: Duration::ZERO, data: stderr.as_bytes().to_vec() }],
+        }
+    }
+
+    /// The failing stage and its return value, or the reason the output was rejected.
+    fn check(success: &Success, codes: &[i32], stderr: &str) -> Result<(), Result<(usize, i32), String>> {
+        success.check(&output(codes, stderr)).map_err(|failure| match failure {
+            Failure::Status(stage, status) => Ok((stage, status.code().unwrap())),
+            Failure::Rejected(reason) => Err(reason),
+        })
+    }
+
+    #[test]
+    fn default_accepts_zero() {
+        let success = Success::default();
+        assert_eq!(check(&success, &[0], ""), Ok(()));
+        assert_eq!(check(&success, &[1], ""), Err(Ok((0, 1))));
+        // The last stage to fail is reported, like pipefail
+        assert_eq!(check(&success, &[2, 0, 3, 0], ""), Err(Ok((2, 3))));
+        assert_eq!(check(&success, &[], ""), Ok(()));
+    }
+
+    #[test]
+    fn success_codes() {
+        let success = Success { exit_codes: vec![1], ..Success::default() };
+        assert_eq!(check(&success, &[0], ""), Ok(()));
+        assert_eq!(check(&success, &[1], ""), Ok(()));
+        assert_eq!(check(&success, &[2], ""), Err(Ok((0, 2))));
+        assert_eq!(check(&success, &[2, 1], ""), Err(Ok((0, 2))));
+        assert_eq!(success.failing_stage(&[status(1), status(0)]), None);
+    }
+
+    #[test]
+    fn predicate_replaces_return_values() {
+        let predicate = |status: ExitStatus, stdout: &[u8], _: &[u8]| status.code() == Some(1) && stdout.is_empty();
+        let success = Success { predicate: Some(Arc::new(predicate)), ..Success::default() };
+        assert_eq!(check(&success, &[1], ""), Ok(()));
+        assert_eq!(check(&success, &[0], ""), Err(Err("with return value 0 was rejected by its success check".to_string())));
+        // A pipeline's status can only be judged once its output is complete
+        assert_eq!(success.failing_stage(&[status(2), status(3)]), None);
+    }
+
+    #[test]
+    fn fail_on_stderr() {
+        let success = Success { stderr_failures: vec!["error:".to_string()], ..Success::default() };
+        assert_eq!(check(&success, &[0], "warning: x\n"), Ok(()));
+        assert_eq!(check(&success, &[0], "a\nerror: x\n"), Err(Err("wrote \"error:\" to stderr".to_string())));
+        // A failing status is reported ahead of stderr
+        assert_eq!(check(&success, &[1], "error: x\n"), Err(Ok((0, 1))));
+    }
+
+    #[test]
+    fn predicate_disables_early_termination() {
+        // The background sleep keeps the pipes open after the shell exits with 1
+        let cmd = "sleep 2 & exit 1";
+        let start = std::time::Instant::now();
+        assert!(SimpleCommand::new("sh").args(["-c", cmd]).try_run().is_err());
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let start = std::time::Instant::now();
+        SimpleCommand::new("sh").args(["-c", cmd]).success_when(|status, _, _| status.code() == Some(1)).run();
+        assert!(start.elapsed() >= Duration::from_secs(2));
+    }
+}
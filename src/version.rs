@@ -0,0 +1,342 @@
+//! Checks that programs are recent enough before the build relies on them.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::builder::SimpleCommand;
+use crate::error::SimpleCommandError;
+use crate::output::Output;
+use crate::parse::quote;
+
+type Parser = dyn Fn(&str) -> Option<Version> + Send + Sync;
+
+/// The output of every version command run so far, keyed by program and flag.
+static CACHE: Mutex<BTreeMap<(OsString, String), Output>> = Mutex::new(BTreeMap::new());
+
+/// A `major.minor.patch` version, compared numerically.
+///
+/// A pre-release such as the `rc1` of `3.0.0-rc1` sorts before the release itself, and
+/// pre-releases are compared like semver, e.g. `alpha.2 < alpha.10 < beta`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The pre-release, `None` for a release.
+    pub pre: Option<String>,
+}
+
+impl Version {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Version {
+        Version { major, minor, patch, pre: None }
+    }
+
+    /// Finds the first version in `text`, such as `3.21.12` in `libprotoc 3.21.12`.
+    ///
+    /// A version needs at least a major and minor number, a missing patch number is 0.
+    /// A suffix starting with a letter, as in `3.0.0-rc1` or `3.12.0b2`, is a pre-release, while
+    /// other suffixes such as the `-1ubuntu1` of a distribution's package are ignored.
+    pub fn find(text: &str) -> Option<Version> {
+        let chars: Vec<char> = text.chars().collect();
+        (0..chars.len()).find_map(|start| parse(&chars, start).map(|(version, _)| version))
+    }
+
+    /// Finds the version in `text` at the `{}` of `pattern`, e.g. `version "{}"`.
+    fn find_pattern(text: &str, pattern: &str) -> Option<Version> {
+        let (prefix, suffix) = pattern.split_once("{}").expect("Version pattern must contain {}");
+        let chars: Vec<char> = text.chars().collect();
+        let prefix_len = prefix.chars().count();
+        for (i, _) in text.match_indices(prefix) {
+            let start = text[..i].chars().count() + prefix_len;
+            if let Some((version, end)) = parse(&chars, start) {
+                if chars[end..].iter().collect::<String>().starts_with(suffix) {
+                    return Some(version);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Parses a version starting at `start`, returning it along with the index just past it.
+fn parse(chars: &[char], start: usize) -> Option<(Version, usize)> {
+    // Only start at the beginning of a number, not part way through `1.2.3` or `x86_64`
+    if !chars.get(start)?.is_ascii_digit() || (start > 0 && (chars[start - 1].is_ascii_digit() || chars[start - 1] == '.')) {
+        return None;
+    }
+    let number = |i: &mut usize| -> Option<u64> {
+        let start = *i;
+        while *i < chars.len() && chars[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse().ok()
+    };
+
+    let mut i = start;
+    let major = number(&mut i)?;
+    if chars.get(i) != Some(&'.') {
+        return None;
+    }
+    i += 1;
+    let minor = number(&mut i)?;
+    let mut patch = 0;
+    if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+        i += 1;
+        patch = number(&mut i)?;
+    }
+
+    let mut pre = None;
+    let pre_start = if chars.get(i) == Some(&'-') { i + 1 } else { i };
+    if chars.get(pre_start).is_some_and(|c| c.is_ascii_alphabetic()) {
+        let mut end = pre_start;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '.' || chars[end] == '-') {
+            end += 1;
+        }
+        // A version at the end of a sentence is not followed by an empty identifier
+        while chars[end - 1] == '.' || chars[end - 1] == '-' {
+            end -= 1;
+        }
+        pre = Some(chars[pre_start..end].iter().collect());
+        i = end;
+    }
+    Some((Version { major, minor, patch, pre }, i))
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        (self.major, self.minor, self.patch).cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => compare_pre(a, b),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares pre-releases identifier by identifier, numbers numerically and before any text.
+fn compare_pre(a: &str, b: &str) -> Ordering {
+    let mut a_ids = a.split('.');
+    let mut b_ids = b.split('.');
+    loop {
+        let ordering = match (a_ids.next(), b_ids.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{}", pre)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the version of a program by running it with `--version`.
+///
+/// The output is cached for the rest of the build script, so checking the same program again is free.
+///
+/// ```no_run
+/// use simple_command::{Version, VersionCheck};
+///
+/// VersionCheck::new("protoc").at_least(Version::new(3, 21, 0));
+///
+/// // Some tools use a different flag or print other numbers before their version
+/// VersionCheck::new("java")
+///     .flag("-version")
+///     .pattern("version \"{}\"")
+///     .at_least(Version::new(17, 0, 0));
+/// ```
+pub struct VersionCheck {
+    program: OsString,
+    flag: String,
+    parser: Option<Arc<Parser>>,
+}
+
+impl VersionCheck {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> VersionCheck {
+        VersionCheck {
+            program: program.as_ref().to_os_string(),
+            flag: "--version".to_string(),
+            parser: None,
+        }
+    }
+
+    /// Sets the flag that makes the program print its version, by default `--version`.
+    pub fn flag<S: Into<String>>(&mut self, flag: S) -> &mut VersionCheck {
+        self.flag = flag.into();
+        self
+    }
+
+    /// Takes the version from where `{}` appears in `pattern`, e.g. `version "{}"` for
+    /// `openjdk version "17.0.2" 2022-01-18`.
+    ///
+    /// The text around the `{}` must match exactly, and the first place it does is used.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` does not contain `{}`.
+    pub fn pattern<S: Into<String>>(&mut self, pattern: S) -> &mut VersionCheck {
+        let pattern = pattern.into();
+        assert!(pattern.contains("{}"), "Version pattern must contain {{}}: {}", pattern);
+        self.parser(move |output| Version::find_pattern(output, &pattern))
+    }
+
+    /// Extracts the version from the combined stdout and stderr of the program, by default the
+    /// first version found by `Version::find`.
+    pub fn parser<F>(&mut self, parser: F) -> &mut VersionCheck
+        where F: Fn(&str) -> Option<Version> + Send + Sync + 'static
+    {
+        self.parser = Some(Arc::new(parser));
+        self
+    }
+
+    /// Returns the version of the program, panicking if it cannot be determined.
+    pub fn version(&mut self) -> Version {
+        match self.try_version() {
+            Ok(version) => version,
+            Err(err) => panic!("\n{}", err)
+        }
+    }
+
+    /// Returns the version of the program, or why it could not be determined.
+    pub fn try_version(&mut self) -> Result<Version, SimpleCommandError> {
+        let output = self.output()?;
+        let text = output.combined_lossy();
+        let version = match &self.parser {
+            Some(parser) => parser(&text),
+            None => Version::find(&text),
+        };
+        version.ok_or_else(|| SimpleCommandError::VersionNotFound { cmd: self.display(), output })
+    }
+
+    /// Returns the version of the program, panicking with the version found if it is older than
+    /// `minimum`.
+    pub fn at_least(&mut self, minimum: Version) -> Version {
+        match self.try_at_least(minimum) {
+            Ok(version) => version,
+            Err(err) => panic!("\n{}", err)
+        }
+    }
+
+    /// Returns the version of the program, or an error if it is older than `minimum`.
+    pub fn try_at_least(&mut self, minimum: Version) -> Result<Version, SimpleCommandError> {
+        let found = self.try_version()?;
+        if found < minimum {
+            let program = self.program.to_string_lossy().into_owned();
+            return Err(SimpleCommandError::VersionTooOld { program, found, minimum });
+        }
+        Ok(found)
+    }
+
+    fn output(&self) -> Result<Output, SimpleCommandError> {
+        let key = (self.program.clone(), self.flag.clone());
+        if let Some(output) = CACHE.lock().unwrap().get(&key) {
+            return Ok(output.clone());
+        }
+
+        // The lock is not held while running so that checks of other programs are not held up
+        let output = SimpleCommand::new(&self.program).arg(&self.flag).try_run()?;
+        CACHE.lock().unwrap().insert(key, output.clone());
+        Ok(output)
+    }
+
+    fn display(&self) -> String {
+        format!("{} {}", quote(&self.program.to_string_lossy()), quote(&self.flag))
+    }
+}
+
+impl fmt::Debug for VersionCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VersionCheck")
+            .field("program", &self.program)
+            .field("flag", &self.flag)
+            .field("parser", &self.parser.as_ref().map(|_| "Fn"))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pre(major: u64, minor: u64, patch: u64, pre: &str) -> Version {
+        Version { pre: Some(pre.to_string()), ..Version::new(major, minor, patch) }
+    }
+
+    #[test]
+    fn find() {
+        assert_eq!(Version::find("libprotoc 3.21.12"), Some(Version::new(3, 21, 12)));
+        assert_eq!(Version::find("cmake version 3.22.1\n\nCMake suite maintained"), Some(Version::new(3, 22, 1)));
+        assert_eq!(Version::find("v20.11.0"), Some(Version::new(20, 11, 0)));
+        assert_eq!(Version::find("no version here"), None);
+        assert_eq!(Version::find("build 42"), None);
+    }
+
+    #[test]
+    fn find_skips_numbers_within_words() {
+        assert_eq!(Version::find("x86_64-unknown-linux-gnu clang 14.0.6"), Some(Version::new(14, 0, 6)));
+        assert_eq!(Version::find("Target: x86_64 1.2.3"), Some(Version::new(1, 2, 3)));
+    }
+
+    #[test]
+    fn find_missing_patch() {
+        assert_eq!(Version::find("Python 3.12"), Some(Version::new(3, 12, 0)));
+        assert_eq!(Version::find("ninja 1.11."), Some(Version::new(1, 11, 0)));
+    }
+
+    #[test]
+    fn find_pre_release() {
+        assert_eq!(Version::find("protoc 3.0.0-rc1"), Some(pre(3, 0, 0, "rc1")));
+        assert_eq!(Version::find("Python 3.13.0b2"), Some(pre(3, 13, 0, "b2")));
+        assert_eq!(Version::find("tool 1.2.3-beta.2+build.5"), Some(pre(1, 2, 3, "beta.2")));
+        assert_eq!(Version::find("tool 2.0-alpha."), Some(pre(2, 0, 0, "alpha")));
+        // Distribution revisions are not pre-releases
+        assert_eq!(Version::find("clang version 14.0.0-1ubuntu1"), Some(Version::new(14, 0, 0)));
+    }
+
+    #[test]
+    fn pre_releases_sort_before_release() {
+        assert!(pre(3, 0, 0, "rc1") < Version::new(3, 0, 0));
+        assert!(pre(3, 0, 0, "rc1") > Version::new(2, 9, 9));
+        assert!(pre(1, 0, 0, "alpha") < pre(1, 0, 0, "alpha.1"));
+        assert!(pre(1, 0, 0, "alpha.2") < pre(1, 0, 0, "alpha.10"));
+        assert!(pre(1, 0, 0, "alpha.10") < pre(1, 0, 0, "beta"));
+        assert!(pre(1, 0, 0, "rc.1") < pre(1, 0, 0, "rc.a"));
+        assert_eq!(pre(3, 0, 0, "rc1").to_string(), "3.0.0-rc1");
+    }
+
+    #[test]
+    fn find_pattern() {
+        let java = "openjdk version \"17.0.2\" 2022-01-18\nOpenJDK Runtime Environment (build 17.0.2+8-86)";
+        assert_eq!(Version::find_pattern(java, "version \"{}\""), Some(Version::new(17, 0, 2)));
+        assert_eq!(Version::find_pattern("gcc (GCC 4.8.5) 11.2.0", ") {}"), Some(Version::new(11, 2, 0)));
+        // Every occurrence of the surrounding text is tried until one matches
+        assert_eq!(Version::find_pattern("version: unknown\nversion: 1.4", "version: {}"), Some(Version::new(1, 4, 0)));
+        assert_eq!(Version::find_pattern("version 1.2.3", "version \"{}\""), None);
+        assert_eq!(Version::find_pattern("v1.2.3", "v{}"), Some(Version::new(1, 2, 3)));
+    }
+}
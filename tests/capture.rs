@@ -0,0 +1,11 @@
+use simple_command::try_simple_command;
+
+// Regression test for a deadlock where stdout and stderr were read one after the other,
+// leaving the child blocked on a full stderr pipe while we waited for stdout.
+#[cfg(unix)]
+#[test]
+fn large_stderr_before_stdout() {
+    let output = try_simple_command("sh -c 'head -c 2000000 /dev/zero | tr \"\\0\" e >&2; echo done'").unwrap();
+    assert_eq!(output.stderr().len(), 2_000_000);
+    assert_eq!(output.stdout(), b"done\n");
+}
@@ -0,0 +1,72 @@
+#![cfg(unix)]
+
+use std::time::{Duration, Instant};
+
+use simple_command::{try_simple_command, SimpleCommandError};
+
+#[test]
+fn stdout_flows_through_every_stage() {
+    let output = try_simple_command("printf 'b\\na\\nb\\n' | sort | uniq -c | wc -l").unwrap();
+    assert_eq!(output.stdout_lossy().trim(), "2");
+    assert_eq!(output.stages().len(), 4);
+}
+
+#[test]
+fn stderr_of_every_stage_is_captured() {
+    let output = try_simple_command("sh -c 'echo one >&2; echo x' | sh -c 'cat; echo two >&2'").unwrap();
+    let stderr = output.stderr_lossy();
+    assert!(stderr.contains("one\n") && stderr.contains("two\n"), "{}", stderr);
+    assert_eq!(output.stdout_lossy(), "x\n");
+}
+
+#[test]
+fn last_failing_stage_is_reported() {
+    let err = try_simple_command("sh -c 'exit 2' | sh -c 'cat; exit 3' | cat").unwrap_err();
+    match &err {
+        SimpleCommandError::NonZeroExit { code, stage, output, .. } => {
+            assert_eq!((*code, *stage), (3, 1));
+            assert_eq!(output.status().code(), Some(3));
+        }
+        err => panic!("unexpected error {}", err),
+    }
+    let message = err.to_string();
+    assert!(message.starts_with("Command \"sh -c 'exit 2' | sh -c 'cat; exit 3' | cat\" failed with return value 3\n"), "{}", message);
+    assert!(message.contains("\nStage 2 \"sh -c 'cat; exit 3'\" failed with return value 3\n"), "{}", message);
+}
+
+#[test]
+fn failing_upstream_is_reported_after_later_stages_finish() {
+    // The first stage fails straight away while the last keeps running and then succeeds
+    let start = Instant::now();
+    let err = try_simple_command("sh -c 'echo x; exit 4' | sh -c 'sleep 0.5; cat'").unwrap_err();
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    match &err {
+        SimpleCommandError::NonZeroExit { code, stage, output, .. } => {
+            assert_eq!((*code, *stage), (4, 0));
+            assert_eq!(output.stdout_lossy(), "x\n");
+            assert!(output.stages()[1].status.success());
+        }
+        err => panic!("unexpected error {}", err),
+    }
+    assert!(err.to_string().contains("\nStage 1 \"sh -c 'echo x; exit 4'\" failed with return value 4\n"), "{}", err);
+}
+
+#[test]
+fn upstream_killed_by_sigpipe_fails() {
+    // Like `set -o pipefail`, a writer killed because the reader exited early fails the pipeline
+    let err = try_simple_command("yes | head -1").unwrap_err();
+    match &err {
+        SimpleCommandError::KilledBySignal { stage, signal, output, .. } => {
+            assert_eq!((*stage, *signal), (0, Some(13)));
+            assert_eq!(output.stdout_lossy(), "y\n");
+        }
+        err => panic!("unexpected error {}", err),
+    }
+    assert!(err.to_string().contains("\nStage 1 \"yes\" was killed by signal 13 (SIGPIPE)\n"), "{}", err);
+}
+
+#[test]
+fn single_command_has_no_stage_line() {
+    let err = try_simple_command("sh -c 'exit 1'").unwrap_err();
+    assert!(!err.to_string().contains("Stage"), "{}", err);
+}
@@ -0,0 +1,54 @@
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::time::Duration;
+
+use simple_command::{SimpleCommand, SimpleCommandError};
+
+/// Returns true if the process exists and is not a zombie waiting to be reaped.
+fn alive(pid: &str) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        // The state follows the parenthesised command name, e.g. `1234 (sleep) S ...`
+        Ok(stat) => !stat.rsplit(')').next().unwrap().trim_start().starts_with('Z'),
+        Err(_) => false,
+    }
+}
+
+// Each descendant redirects its output so it cannot hold the pipes open,
+// otherwise a surviving descendant would only show up as a slow test.
+
+/// Extracts the pid the command printed to stdout before failing.
+fn printed_pid(err: &SimpleCommandError) -> String {
+    let output = match err {
+        SimpleCommandError::NonZeroExit { output, .. } => output,
+        SimpleCommandError::TimedOut { output, .. } => output,
+        err => panic!("unexpected error {}", err),
+    };
+    output.stdout_lossy().trim().to_string()
+}
+
+#[test]
+fn descendants_killed_on_failure() {
+    let err = SimpleCommand::parse("sh -c 'sleep 30 >/dev/null 2>&1 & echo $!; exit 1'").unwrap().try_run().unwrap_err();
+    let pid = printed_pid(&err);
+    assert!(!alive(&pid), "descendant {} survived", pid);
+}
+
+#[test]
+fn descendants_killed_on_timeout() {
+    let err = SimpleCommand::parse("sh -c 'sleep 30 >/dev/null 2>&1 & echo $!; sleep 30'").unwrap()
+        .timeout(Duration::from_millis(200))
+        .try_run()
+        .unwrap_err();
+    let pid = printed_pid(&err);
+    assert!(!alive(&pid), "descendant {} survived", pid);
+}
+
+#[test]
+fn descendants_ignoring_sigterm_killed() {
+    let err = SimpleCommand::parse("sh -c 'sh -c \"trap \\\"\\\" TERM; sleep 30\" >/dev/null 2>&1 & echo $!; exit 1'").unwrap()
+        .try_run()
+        .unwrap_err();
+    let pid = printed_pid(&err);
+    assert!(!alive(&pid), "descendant {} survived", pid);
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use simple_command::{quote, try_simple_command};
+
+/// A fresh, empty directory for a test.
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("simple_command_redirect_{}_{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path(dir: &Path, name: &str) -> String {
+    quote(&dir.join(name).to_string_lossy())
+}
+
+/// The names of the files in `dir`, sorted.
+fn files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(unix)]
+#[test]
+fn failed_command_leaves_target_unchanged() {
+    let dir = dir("failed");
+    fs::write(dir.join("f"), "before\n").unwrap();
+    for operator in [">", ">>", "2>"] {
+        let cmd = format!("sh -c 'echo partial; echo partial >&2; exit 1' {} {}", operator, path(&dir, "f"));
+        assert!(try_simple_command(&cmd).is_err());
+        assert_eq!(fs::read_to_string(dir.join("f")).unwrap(), "before\n", "{}", operator);
+    }
+    // Nor is a new file created
+    assert!(try_simple_command(&format!("sh -c 'echo partial; exit 1' > {}", path(&dir, "new"))).is_err());
+    assert_eq!(files(&dir), ["f"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn truncate_and_append() {
+    let dir = dir("append");
+    let f = path(&dir, "f");
+    try_simple_command(&format!("echo a > {}", f)).unwrap();
+    try_simple_command(&format!("echo b >> {}", f)).unwrap();
+    try_simple_command(&format!("echo c >> {}", path(&dir, "new"))).unwrap();
+    assert_eq!(fs::read_to_string(dir.join("f")).unwrap(), "a\nb\n");
+    assert_eq!(fs::read_to_string(dir.join("new")).unwrap(), "c\n");
+
+    try_simple_command(&format!("echo d > {}", f)).unwrap();
+    assert_eq!(fs::read_to_string(dir.join("f")).unwrap(), "d\n");
+    assert_eq!(files(&dir), ["f", "new"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn stdout_and_stderr() {
+    let dir = dir("streams");
+    let script = "sh -c 'echo out; echo err >&2; echo out2'";
+
+    let output = try_simple_command(&format!("{} > {} 2> {}", script, path(&dir, "out"), path(&dir, "err"))).unwrap();
+    assert!(output.stdout().is_empty() && output.stderr().is_empty());
+    assert_eq!(fs::read_to_string(dir.join("out")).unwrap(), "out\nout2\n");
+    assert_eq!(fs::read_to_string(dir.join("err")).unwrap(), "err\n");
+
+    // Only stderr is redirected, stdout is still captured
+    let output = try_simple_command(&format!("{} 2> {}", script, path(&dir, "err"))).unwrap();
+    assert_eq!(output.stdout_lossy(), "out\nout2\n");
+
+    // Both streams share one file, in the order they were written
+    try_simple_command(&format!("{} > {} 2> {}", script, path(&dir, "both"), path(&dir, "both"))).unwrap();
+    assert_eq!(fs::read_to_string(dir.join("both")).unwrap(), "out\nerr\nout2\n");
+    assert_eq!(files(&dir), ["both", "err", "out"]);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn keeps_permissions_and_symlinks() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let dir = dir("metadata");
+    fs::write(dir.join("script"), "").unwrap();
+    fs::set_permissions(dir.join("script"), fs::Permissions::from_mode(0o750)).unwrap();
+    try_simple_command(&format!("echo 'echo hi' > {}", path(&dir, "script"))).unwrap();
+    assert_eq!(fs::metadata(dir.join("script")).unwrap().permissions().mode() & 0o777, 0o750);
+
+    symlink("script", dir.join("link")).unwrap();
+    try_simple_command(&format!("echo 'echo linked' >> {}", path(&dir, "link"))).unwrap();
+    assert!(fs::symlink_metadata(dir.join("link")).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_to_string(dir.join("script")).unwrap(), "echo hi\necho linked\n");
+
+    symlink("target", dir.join("dangling")).unwrap();
+    try_simple_command(&format!("echo new > {}", path(&dir, "dangling"))).unwrap();
+    assert_eq!(fs::read_to_string(dir.join("target")).unwrap(), "new\n");
+    assert_eq!(files(&dir), ["dangling", "link", "script", "target"]);
+    fs::remove_dir_all(dir).unwrap();
+}